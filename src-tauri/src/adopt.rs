@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use semver::Version;
+use serde::Serialize;
+
+use crate::disk;
+use crate::install::Install;
+use crate::manifest::{DownloadStrategy, Product, VersionProbe};
+
+/// A copy of a product in its install directory that isn't recorded as installed
+#[derive(Clone, Serialize)]
+pub struct Existing {
+    /// The directory the copy is in
+    pub directory: String,
+    /// The version of the copy, if it could be identified
+    pub version: Option<Version>,
+}
+
+/// Look for a copy of `prod` where it would be installed, if it isn't recorded
+/// as installed already. Only copies that can be recognised by their version
+/// probe or one of the product's executables count, so files left behind by
+/// a removal aren't mistaken for one.
+pub fn find(install: &Install, prod: &Product) -> Option<Existing> {
+    let prod_install = install.products().get(prod.id());
+    if prod_install.is_some_and(|p| p.version().is_some()) {
+        return None;
+    }
+    let directory = install
+        .install_root_for(prod.id())
+        .join(prod.install_directory());
+    let directory_str = directory.to_string_lossy().to_string();
+    if prod_install.is_some_and(|p| p.removed_from().as_ref() == Some(&directory_str)) {
+        return None;
+    }
+    let version = identify(prod, &directory);
+    if version.is_none() && !has_executable(prod, &directory) {
+        return None;
+    }
+    Some(Existing {
+        directory: directory_str,
+        version,
+    })
+}
+
+/// Does `directory` hold the executable of any version of `prod` for this system?
+fn has_executable(prod: &Product, directory: &Path) -> bool {
+    let executables: BTreeSet<_> = prod
+        .versions()
+        .iter()
+        .filter_map(|v| v.downloads().for_this_system())
+        .filter_map(|d| d.executable().clone())
+        .collect();
+    executables
+        .iter()
+        .any(|exec| directory.join(exec).is_file())
+}
+
+/// Work out which version of `prod` is in `directory`, using its version
+/// probe. Only versions in the manifest are recognised.
+pub fn identify(prod: &Product, directory: &Path) -> Option<Version> {
+    let version = probe(prod.version_probe().as_ref()?, directory)?;
+    prod.versions()
+        .iter()
+        .any(|v| *v.version() == version)
+        .then_some(version)
+}
+
+fn probe(probe: &VersionProbe, directory: &Path) -> Option<Version> {
+    let contents = fs::read_to_string(directory.join(probe.file())).ok()?;
+    let pattern = probe
+        .pattern()
+        .as_deref()
+        .unwrap_or(r"(\d+\.\d+\.\d+(?:-[0-9A-Za-z.-]+)?)");
+    let re = Regex::new(pattern)
+        .inspect_err(|e| tracing::warn!("Invalid version probe pattern {pattern:?}: {e}"))
+        .ok()?;
+    let captures = re.captures(&contents)?;
+    let found = captures.get(1).or(captures.get(0))?.as_str().trim();
+    Version::parse(found.trim_start_matches('v')).ok()
+}
+
+/// Record the copy of `prod` in its install directory as installed, so it can
+/// be updated and removed like anything else. Its files aren't recorded, as
+/// there's no telling which were changed since it was put there.
+pub fn adopt(install: &mut Install, prod: &Product) -> Result<Version, String> {
+    let existing = find(install, prod).ok_or(format!("No copy of {} was found", prod.name()))?;
+    let version = existing.version.ok_or(format!(
+        "The version of {} in {} couldn't be identified",
+        prod.name(),
+        existing.directory
+    ))?;
+    let download = prod
+        .version_data(&version)
+        .ok_or(format!("v{version} isn't available for this system"))?;
+    if matches!(download.strategy(), DownloadStrategy::Msi { .. }) {
+        return Err("Products installed by an MSI can't be adopted".to_string());
+    }
+
+    let directory = Path::new(&existing.directory);
+    let prod_install = install.get_mut_product_or_default(prod.id().clone());
+    prod_install.set_name(prod.name().clone());
+    prod_install.set_description(prod.description().clone());
+    prod_install.set_icon(prod.icon().clone());
+    prod_install.set_version(Some(version.to_string()));
+    prod_install.set_installed_size(disk::directory_size(directory).ok());
+    let main_executable = download
+        .executable_absolute()
+        .as_deref()
+        .map(super::expand_env_vars)
+        .or(download
+            .executable()
+            .as_ref()
+            .map(|exec| directory.join(exec).to_string_lossy().to_string()));
+    if main_executable.is_some() {
+        prod_install.set_main_executable(main_executable);
+        prod_install.set_execute_working_directory(Some(existing.directory.clone()));
+    }
+    prod_install.set_install_directory(Some(existing.directory));
+    prod_install.set_removed_from(None);
+    prod_install.set_user_data(prod.user_data().clone());
+    Ok(version)
+}
@@ -0,0 +1,133 @@
+use serde::Serialize;
+
+use crate::install::Install;
+use crate::manifest::Manifest;
+use crate::profile::{self, Action, Profile, SettingChange};
+
+/// How far a step towards the desired state got
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// Not carried out, as this is a dry run
+    Planned,
+    Done,
+    Failed,
+}
+
+/// An install, update, downgrade or removal needed to reach the desired state
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    #[serde(flatten)]
+    pub action: Action,
+    pub status: Status,
+    /// Why the step failed, if it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What was done, or would be done, to make this machine match a profile
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub dry_run: bool,
+    /// Was anything changed, or would anything be changed in a dry run?
+    pub changed: bool,
+    /// Products whose channel or pin was changed
+    pub settings: Vec<SettingChange>,
+    /// The names of environment variables that were set
+    pub environment: Vec<String>,
+    pub steps: Vec<Step>,
+    /// Parts of the profile that can't be matched
+    pub problems: Vec<String>,
+}
+
+impl Report {
+    /// Did the machine end up matching the profile?
+    pub fn succeeded(&self) -> bool {
+        self.problems.is_empty() && self.steps.iter().all(|s| s.status != Status::Failed)
+    }
+}
+
+/// Install, update, pin and remove products until this machine matches
+/// `profile`. Running it again once it has succeeded changes nothing. Nothing
+/// is changed at all in a `dry_run`.
+pub async fn apply(
+    profile: &Profile,
+    manifest: &Manifest,
+    install: &mut Install,
+    dry_run: bool,
+) -> Result<Report, String> {
+    let environment_file = super::local_environment_file();
+    let current_environment = profile::read_environment(&environment_file);
+    let plan = profile.plan(manifest, install);
+    let mut report = Report {
+        dry_run,
+        changed: false,
+        settings: profile.setting_changes(manifest, install),
+        environment: profile
+            .environment
+            .iter()
+            .filter(|(key, value)| current_environment.get(*key) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect(),
+        steps: vec![],
+        problems: plan.problems,
+    };
+    report.changed =
+        !report.settings.is_empty() || !report.environment.is_empty() || !plan.actions.is_empty();
+    if dry_run {
+        report.steps = plan
+            .actions
+            .into_iter()
+            .map(|action| Step {
+                action,
+                status: Status::Planned,
+                error: None,
+            })
+            .collect();
+        return Ok(report);
+    }
+
+    profile.apply_settings(manifest, install);
+    install
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    if !report.environment.is_empty() {
+        profile::write_environment(&environment_file, &profile.environment)
+            .map_err(|e| format!("Failed to save environment variables: {e}"))?;
+    }
+
+    // Removals go first to make room for everything else
+    let (removals, others): (Vec<_>, Vec<_>) = plan
+        .actions
+        .into_iter()
+        .partition(|a| matches!(a, Action::Remove { .. }));
+    for action in removals.into_iter().chain(others) {
+        // A failed step may leave the install data half changed
+        let before = install.clone();
+        let result = match &action {
+            Action::Remove { id, .. } => {
+                let prod = manifest.products().iter().find(|p| p.id() == id);
+                super::remove_product(install, prod, id, false)
+            }
+            Action::Install { id, .. }
+            | Action::Update { id, .. }
+            | Action::Downgrade { id, .. } => super::install_product(install, manifest, id, None)
+                .await
+                .map(|_| ()),
+        };
+        if let Err(e) = &result {
+            tracing::error!("Failed to reach the desired state: {e}");
+            *install = before;
+        }
+        report.steps.push(Step {
+            action,
+            status: if result.is_ok() {
+                Status::Done
+            } else {
+                Status::Failed
+            },
+            error: result.err(),
+        });
+    }
+    Ok(report)
+}
@@ -0,0 +1,234 @@
+use std::env;
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use reqwest::header::RANGE;
+use reqwest::{StatusCode, Url};
+
+use crate::disk;
+use crate::gzip;
+use crate::http;
+use crate::inventory;
+use crate::manifest::{DownloadSpec, DownloadStrategy};
+
+/// How many times to try each source before moving on to the next
+const ATTEMPTS_PER_SOURCE: u32 = 3;
+/// How long to wait before the first retry. This doubles for each retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// How long a single attempt may take unless `ANGELSUITE_DOWNLOAD_TIMEOUT` says otherwise
+const DEFAULT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// A successfully downloaded artifact
+pub struct Downloaded {
+    /// Where the artifact was downloaded to
+    pub path: PathBuf,
+    /// The URL the artifact was downloaded from
+    pub source: String,
+}
+
+/// Download the artifact described by `download` into `directory`. Each of
+/// the download's sources is tried in order, retrying with exponential backoff
+/// and resuming from what has already been downloaded where the server allows.
+pub async fn download(download: &DownloadSpec, directory: &Path) -> Result<Downloaded, String> {
+    let path = directory.join("data");
+    let mut last_error = "No download sources".to_string();
+    for source in download.sources() {
+        // A partial download from one source can't be trusted to match another
+        let _ = fs::remove_file(&path);
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=ATTEMPTS_PER_SOURCE {
+            tracing::debug!(
+                "Downloading from {source} (attempt {attempt} of {ATTEMPTS_PER_SOURCE})"
+            );
+            match download_attempt(download, source, &path).await {
+                Ok(()) => {
+                    tracing::info!("Downloaded from {source}");
+                    return Ok(Downloaded {
+                        path,
+                        source: source.clone(),
+                    });
+                }
+                Err(e) => {
+                    tracing::warn!("Download attempt {attempt} from {source} failed: {e}");
+                    last_error = e;
+                }
+            }
+            if attempt < ATTEMPTS_PER_SOURCE {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+    }
+    Err(format!("Failed to get data: {last_error}"))
+}
+
+async fn download_attempt(download: &DownloadSpec, url: &str, path: &Path) -> Result<(), String> {
+    if let Some(source) = local_path(url) {
+        tracing::debug!("Copying from {source:?}");
+        fs::copy(&source, path).map_err(|e| format!("Failed to copy {}: {e}", source.display()))?;
+        return verify(download, path);
+    }
+
+    let existing = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let mut request = http::client().get(url).timeout(attempt_timeout());
+    if existing > 0 {
+        tracing::debug!("Resuming from {existing} bytes");
+        request = request.header(RANGE, format!("bytes={existing}-"));
+    }
+    let mut req = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to get data: {e}"))?;
+    tracing::debug!("download response: {req:?}");
+
+    if existing > 0 && req.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // We already have the whole file
+        return verify(download, path);
+    }
+    let req_status = req.status();
+    req.error_for_status_ref()
+        .map_err(|e| format!("Server responded with {req_status}: {e}"))?;
+
+    let resuming = existing > 0 && req_status == StatusCode::PARTIAL_CONTENT;
+    if existing > 0 && !resuming {
+        tracing::debug!("Server can't resume, starting over");
+    }
+    let already_downloaded = if resuming { existing } else { 0 };
+    if let Some(size) = download
+        .size()
+        .or(req.content_length().map(|l| l + already_downloaded))
+    {
+        // The download is staged next to itself before being installed
+        let staged_size = download.installed_size().unwrap_or(0);
+        let directory = path.parent().unwrap_or(path);
+        disk::ensure_free_space(
+            directory,
+            size.saturating_sub(already_downloaded) + staged_size,
+        )?;
+    }
+
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resuming)
+        .truncate(!resuming)
+        .open(path)
+        .map_err(|e| format!("Failed to create temporary file: {e}"))?;
+    let mut writer = BufWriter::new(file);
+    while let Some(data) = req
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to get data: {e}"))?
+    {
+        writer
+            .write_all(&data)
+            .map_err(|e| format!("Failed to write data: {e}"))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write data: {e}"))?;
+    tracing::debug!("File downloaded");
+    verify(download, path)
+}
+
+/// Check a downloaded file against the size and hash in its download spec,
+/// removing it if it doesn't match so the next attempt starts afresh.
+fn verify(download: &DownloadSpec, path: &Path) -> Result<(), String> {
+    let result = check(download, path);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn check(download: &DownloadSpec, path: &Path) -> Result<(), String> {
+    if let Some(size) = download.size() {
+        let actual = fs::metadata(path)
+            .map_err(|e| format!("Failed to read downloaded file: {e}"))?
+            .len();
+        if actual != *size {
+            return Err(format!(
+                "Downloaded file is {actual} bytes but {size} bytes were expected"
+            ));
+        }
+    }
+    if let Some(sha256) = download.sha256() {
+        let actual = inventory::hash_file(path)
+            .map_err(|e| format!("Failed to read downloaded file: {e}"))?;
+        if !actual.eq_ignore_ascii_case(sha256) {
+            return Err(format!(
+                "Downloaded file has SHA-256 {actual} but {sha256} was expected"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// If `url` is a `file://` URL, get the path it points to.
+fn local_path(url: &str) -> Option<PathBuf> {
+    Url::parse(url)
+        .ok()
+        .filter(|u| u.scheme() == "file")
+        .and_then(|u| u.to_file_path().ok())
+}
+
+fn attempt_timeout() -> Duration {
+    env::var("ANGELSUITE_DOWNLOAD_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_ATTEMPT_TIMEOUT)
+}
+
+/// Unpack a downloaded `file` into `staging` according to `strategy`, so we
+/// know exactly which files make up the product. MSIs can't be staged and
+/// must be handed to `msiexec` instead.
+pub fn stage(strategy: &DownloadStrategy, file: &Path, staging: &Path) -> Result<(), String> {
+    fs::create_dir_all(staging).map_err(|e| format!("Failed to create staging directory: {e}"))?;
+    match strategy {
+        DownloadStrategy::File { name, chmod } => {
+            let mut path = staging.to_path_buf();
+            path.push(name);
+
+            fs::copy(file, &path).map_err(|e| format!("Failed to create target file: {e}"))?;
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                if *chmod {
+                    tracing::debug!("chmod'ing file");
+                    let mut perms = fs::metadata(&path)
+                        .map_err(|e| format!("Failed to set permissions: {e}"))?
+                        .permissions();
+                    perms.set_mode(perms.mode() | 0o100);
+                    fs::set_permissions(path, perms)
+                        .map_err(|e| format!("Failed to set permissions: {e}"))?;
+                }
+            }
+            #[cfg(not(unix))]
+            {
+                if *chmod {
+                    // Do nothing, just to shut up compiler warnings…
+                }
+            }
+        }
+        DownloadStrategy::Msi { .. } => {
+            return Err("MSI packages cannot be staged".to_string());
+        }
+        DownloadStrategy::ZipFile => {
+            let reader = BufReader::new(
+                fs::File::open(file).map_err(|e| format!("Failed to open temporary file: {e}"))?,
+            );
+            zip_extract::extract(reader, staging, true)
+                .map_err(|e| format!("Failed to extract data: {e}"))?;
+        }
+        DownloadStrategy::GzippedTarball => {
+            gzip::extract_tar_gz(file, staging)
+                .map_err(|e| format!("Failed to extract data: {e}"))?;
+        }
+    }
+    Ok(())
+}
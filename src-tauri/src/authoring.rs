@@ -0,0 +1,348 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use semver::Version;
+use serde_json::Value;
+use tauri::Url;
+
+use crate::artifact;
+use crate::disk;
+use crate::inventory;
+use crate::manifest::{DownloadSpec, DownloadStrategy, Platform, ProductDownloads, ProductVersion};
+
+/// A built artifact for a platform, given on the command line as `platform=path-or-url`
+#[derive(Clone, Debug)]
+pub struct ArtifactArg {
+    platform: Platform,
+    location: String,
+}
+
+impl FromStr for ArtifactArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (platform, location) = s
+            .split_once('=')
+            .ok_or(format!("expected PLATFORM=PATH or PLATFORM=URL, got {s:?}"))?;
+        Ok(Self {
+            platform: platform.parse()?,
+            location: location.to_string(),
+        })
+    }
+}
+
+/// The details of a new release, besides its artifacts
+pub struct Release {
+    /// The product to add the version to
+    pub id: String,
+    /// The new version
+    pub version: Version,
+    /// Release notes, as Markdown or a URL
+    pub release_notes: Option<String>,
+    /// When the version was released, defaulting to today
+    pub released_at: Option<String>,
+    /// The oldest installer that can install this version
+    pub requires_installer: Option<Version>,
+    /// The executable to start, relative to the install directory, if it can't be worked out
+    pub executable: Option<String>,
+    /// The MSI product code, needed for MSI artifacts
+    pub msi_product_code: Option<String>,
+    /// The URL local artifacts will be uploaded to, which their file names are appended to
+    pub base_url: Option<String>,
+    /// Refer to local artifacts by their path from the manifest instead of a URL
+    pub relative: bool,
+    /// Replace the version if it is already in the manifest
+    pub replace: bool,
+}
+
+/// Inspect each artifact and add a version made from them to the manifest at
+/// `manifest_path`. The manifest is edited as JSON so anything this installer
+/// doesn't understand is kept as it is.
+pub async fn add_version(
+    manifest_path: &Path,
+    release: &Release,
+    artifacts: &[ArtifactArg],
+) -> Result<(), String> {
+    if artifacts.is_empty() {
+        return Err("At least one artifact is needed".to_string());
+    }
+    let mut manifest: Value = serde_json::from_slice(
+        &fs::read(manifest_path).map_err(|e| format!("Failed to open manifest: {e}"))?,
+    )
+    .map_err(|e| format!("Failed to read manifest: {e}"))?;
+    let manifest_dir = manifest_path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()
+        .map_err(|e| format!("Failed to find manifest directory: {e}"))?;
+
+    let mut downloads = ProductDownloads::default();
+    for artifact in artifacts {
+        tracing::info!(
+            "Inspecting {} artifact {}",
+            artifact.platform,
+            artifact.location
+        );
+        let spec = inspect(artifact, release, &manifest_dir)
+            .await
+            .map_err(|e| format!("{} artifact {}: {e}", artifact.platform, artifact.location))?;
+        downloads.set_for_platform(artifact.platform, Some(spec));
+    }
+
+    let mut version = ProductVersion::new(release.version.clone(), downloads);
+    version.set_release_notes(release.release_notes.clone());
+    version.set_released_at(Some(release.released_at.clone().unwrap_or_else(today)));
+    version.set_requires_installer(release.requires_installer.clone());
+    let mut version =
+        serde_json::to_value(version).map_err(|e| format!("Failed to write version: {e}"))?;
+    remove_nulls(&mut version);
+
+    let product = manifest
+        .get_mut("products")
+        .and_then(Value::as_array_mut)
+        .and_then(|products| {
+            products
+                .iter_mut()
+                .find(|p| p.get("id").and_then(Value::as_str) == Some(&release.id))
+        })
+        .ok_or(format!("No product with ID {}", release.id))?;
+    let versions = product
+        .get_mut("versions")
+        .and_then(Value::as_array_mut)
+        .ok_or(format!("{} has no versions list", release.id))?;
+    let existing = versions.iter().position(|v| {
+        v.get("version")
+            .and_then(Value::as_str)
+            .and_then(|v| Version::parse(v).ok())
+            .is_some_and(|v| v == release.version)
+    });
+    match existing {
+        Some(i) if release.replace => versions[i] = version,
+        Some(_) => {
+            return Err(format!(
+                "{} already has version {}, use --replace to overwrite it",
+                release.id, release.version
+            ))
+        }
+        None => versions.push(version),
+    }
+
+    let mut json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to write manifest: {e}"))?;
+    json.push('\n');
+    fs::write(manifest_path, json).map_err(|e| format!("Failed to write manifest: {e}"))?;
+    tracing::info!(
+        "Added {} v{} to {manifest_path:?}",
+        release.id,
+        release.version
+    );
+    Ok(())
+}
+
+/// Work out the download spec for an artifact: where it will be downloaded
+/// from, how to install it, what to start and how big it is.
+async fn inspect(
+    artifact: &ArtifactArg,
+    release: &Release,
+    manifest_dir: &Path,
+) -> Result<DownloadSpec, String> {
+    let is_url =
+        artifact.location.starts_with("http://") || artifact.location.starts_with("https://");
+    let file_name = artifact
+        .location
+        .split(['?', '#'])
+        .next()
+        .and_then(|l| l.rsplit(['/', '\\']).next())
+        .filter(|n| !n.is_empty())
+        .ok_or("can't tell the file name")?
+        .to_string();
+
+    let strategy = strategy_for(&file_name, artifact.platform, release)?;
+    let dir =
+        tempfile::tempdir().map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+    let (url, file) = if is_url {
+        let spec = DownloadSpec::new(artifact.location.clone(), strategy.clone());
+        let downloaded = artifact::download(&spec, dir.path()).await?;
+        (artifact.location.clone(), downloaded.path)
+    } else {
+        let file = PathBuf::from(&artifact.location)
+            .canonicalize()
+            .map_err(|e| format!("can't be read: {e}"))?;
+        (url_for(&file, &file_name, release, manifest_dir)?, file)
+    };
+
+    let mut spec = DownloadSpec::new(url, strategy.clone());
+    spec.set_size(Some(
+        fs::metadata(&file)
+            .map_err(|e| format!("can't be read: {e}"))?
+            .len(),
+    ));
+    spec.set_sha256(Some(
+        inventory::hash_file(&file).map_err(|e| format!("can't be read: {e}"))?,
+    ));
+
+    if matches!(strategy, DownloadStrategy::Msi { .. }) {
+        // MSIs install wherever they like, so there is nothing more to learn from them
+        return Ok(spec);
+    }
+    let staging = dir.path().join("staging");
+    artifact::stage(&strategy, &file, &staging)?;
+    spec.set_installed_size(Some(
+        disk::directory_size(&staging).map_err(|e| format!("Failed to measure artifact: {e}"))?,
+    ));
+    let executable = match &release.executable {
+        Some(executable) if staging.join(executable).is_file() => executable.clone(),
+        Some(executable) => return Err(format!("doesn't contain {executable:?}")),
+        None => find_executable(&staging, artifact.platform, &release.id)?,
+    };
+    spec.set_executable(Some(executable));
+    Ok(spec)
+}
+
+/// Pick an install strategy from an artifact's file name.
+fn strategy_for(
+    file_name: &str,
+    platform: Platform,
+    release: &Release,
+) -> Result<DownloadStrategy, String> {
+    let lower = file_name.to_lowercase();
+    Ok(if lower.ends_with(".zip") {
+        DownloadStrategy::ZipFile
+    } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        DownloadStrategy::GzippedTarball
+    } else if lower.ends_with(".msi") {
+        DownloadStrategy::Msi {
+            product_code: release
+                .msi_product_code
+                .clone()
+                .ok_or("MSI artifacts need --msi-product-code")?,
+        }
+    } else {
+        DownloadStrategy::File {
+            name: file_name.to_string(),
+            chmod: platform != Platform::Windows,
+        }
+    })
+}
+
+/// Work out the URL a local artifact will be published at: below `--base-url`
+/// if given, otherwise relative to the manifest if `--relative` is given.
+/// Relative URLs only work for manifests read from a file or bundle, so they
+/// have to be asked for.
+fn url_for(
+    file: &Path,
+    file_name: &str,
+    release: &Release,
+    manifest_dir: &Path,
+) -> Result<String, String> {
+    if let Some(base) = &release.base_url {
+        let base = Url::parse(&format!("{}/", base.trim_end_matches('/')))
+            .map_err(|e| format!("--base-url is not a valid URL: {e}"))?;
+        return base
+            .join(file_name)
+            .map(|u| u.to_string())
+            .map_err(|e| format!("can't build its URL: {e}"));
+    }
+    if !release.relative {
+        return Err("needs --base-url to say where it will be published, or --relative if the manifest will only be read from a file or bundle".to_string());
+    }
+    let relative = file.strip_prefix(manifest_dir).map_err(|_| {
+        "isn't next to the manifest, so use --base-url to say where it will be published"
+            .to_string()
+    })?;
+    Ok(relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Find the program to start in a staged artifact. The shallowest executable
+/// for the platform is chosen, preferring one named after the product.
+fn find_executable(staging: &Path, platform: Platform, id: &str) -> Result<String, String> {
+    let files = inventory::list(staging, &[]).map_err(|e| format!("Failed to list files: {e}"))?;
+    let mut candidates: Vec<_> = files
+        .into_iter()
+        .filter(|f| is_executable(&staging.join(f), f, platform))
+        .collect();
+    let depth = |f: &String| f.matches('/').count();
+    let Some(shallowest) = candidates.iter().map(depth).min() else {
+        return Err(format!(
+            "contains no {platform} executable, use --executable to give one"
+        ));
+    };
+    candidates.retain(|f| depth(f) == shallowest);
+    if candidates.len() > 1 {
+        let id = id.to_lowercase();
+        let named: Vec<_> = candidates
+            .iter()
+            .filter(|f| {
+                f.rsplit('/')
+                    .next()
+                    .unwrap_or(f)
+                    .to_lowercase()
+                    .contains(&id)
+            })
+            .cloned()
+            .collect();
+        if !named.is_empty() {
+            candidates = named;
+        }
+    }
+    match candidates.as_slice() {
+        [executable] => Ok(executable.clone()),
+        _ => Err(format!(
+            "has several possible executables ({}), use --executable to choose one",
+            candidates.join(", ")
+        )),
+    }
+}
+
+/// Is this file a program for `platform`? This goes by the file's magic number
+/// rather than permissions, which archives don't always keep.
+fn is_executable(path: &Path, relative: &str, platform: Platform) -> bool {
+    let name = relative
+        .rsplit('/')
+        .next()
+        .unwrap_or(relative)
+        .to_lowercase();
+    if name.ends_with(".dylib") || name.ends_with(".so") || name.contains(".so.") {
+        return false;
+    }
+    let mut magic = [0; 4];
+    let Ok(()) = fs::File::open(path).and_then(|mut f| f.read_exact(&mut magic)) else {
+        return false;
+    };
+    match platform {
+        Platform::Windows => name.ends_with(".exe") && magic[..2] == *b"MZ",
+        Platform::Mac | Platform::MacIntel => matches!(
+            u32::from_be_bytes(magic),
+            0xfeedface | 0xfeedfacf | 0xcefaedfe | 0xcffaedfe | 0xcafebabe
+        ),
+        Platform::Linux => magic == *b"\x7fELF",
+    }
+}
+
+/// Remove `null` fields, so the new version only has what was given.
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => (),
+    }
+}
+
+/// Today's date in ISO 8601 format, in UTC.
+fn today() -> String {
+    // Formatted as YYYY-MM-DDTHH:MM:SSZ
+    let mut now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+    now.truncate("YYYY-MM-DD".len());
+    now
+}
@@ -0,0 +1,317 @@
+use std::fs;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use semver::Version;
+use tempfile::TempDir;
+use zip::write::SimpleFileOptions;
+
+use crate::artifact;
+use crate::gzip;
+use crate::inventory;
+use crate::manifest::{Manifest, ManifestError, Platform, ProductDownloads};
+
+/// The name of the manifest at the root of a bundle
+pub const BUNDLE_MANIFEST: &str = "manifest.json";
+
+/// The archive formats a bundle can be packed into
+#[derive(Clone, Copy)]
+enum ArchiveFormat {
+    Zip,
+    GzippedTarball,
+}
+
+impl ArchiveFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::GzippedTarball)
+        } else {
+            None
+        }
+    }
+}
+
+/// An offline bundle: a manifest plus the artifacts it refers to, either in a
+/// directory or packed into an archive.
+pub struct Bundle {
+    /// The path the bundle was opened from
+    path: PathBuf,
+    /// The directory holding the bundle's contents
+    root: PathBuf,
+    /// Where an archived bundle has been unpacked to, kept for as long as the bundle is open
+    _unpacked: Option<TempDir>,
+}
+
+impl Bundle {
+    /// Open a bundle, unpacking it first if it is an archive.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if path.is_dir() {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                root: path.to_path_buf(),
+                _unpacked: None,
+            });
+        }
+
+        let format = ArchiveFormat::from_path(path).ok_or(format!(
+            "{} is not a bundle directory, .zip or .tar.gz file",
+            path.display()
+        ))?;
+        let unpacked = tempfile::tempdir()
+            .map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+        tracing::info!("Unpacking bundle {path:?}");
+        match format {
+            ArchiveFormat::Zip => {
+                let reader = BufReader::new(
+                    fs::File::open(path).map_err(|e| format!("Failed to open bundle: {e}"))?,
+                );
+                zip_extract::extract(reader, unpacked.path(), true)
+                    .map_err(|e| format!("Failed to unpack bundle: {e}"))?;
+            }
+            ArchiveFormat::GzippedTarball => {
+                gzip::extract_tar_gz(path, unpacked.path())
+                    .map_err(|e| format!("Failed to unpack bundle: {e}"))?;
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            root: unpacked.path().to_path_buf(),
+            _unpacked: Some(unpacked),
+        })
+    }
+
+    /// The path the bundle was opened from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path to the bundle's manifest
+    pub fn manifest_path(&self) -> PathBuf {
+        self.root.join(BUNDLE_MANIFEST)
+    }
+
+    /// Read the bundle's manifest, with every download pointing at the
+    /// artifact inside the bundle.
+    pub fn manifest(&self) -> Result<Manifest, ManifestError> {
+        let bytes = fs::read(self.manifest_path())
+            .map_err(|e| ManifestError::Invalid(format!("Failed to open bundle manifest: {e}")))?;
+        let mut manifest = Manifest::parse(&bytes)?;
+        manifest.resolve_relative_urls(&self.root);
+        Ok(manifest)
+    }
+}
+
+/// Write a bundle of the chosen products to `output`, which is packed into an
+/// archive if it ends in `.zip` or `.tar.gz`, otherwise it is a directory.
+///
+/// Products are chosen by ID, optionally with an `@version` suffix. If no
+/// version is given the latest is used, and if no products are given all
+/// products are included.
+pub async fn export(
+    manifest: &Manifest,
+    output: &Path,
+    selections: &[String],
+    platforms: &[Platform],
+    channel: Option<&str>,
+) -> Result<(), String> {
+    let channel = match channel {
+        Some(id) => manifest
+            .available_channels()
+            .into_iter()
+            .find(|c| c.id() == id)
+            .ok_or(format!("No channel with ID {id}"))?,
+        None => manifest.channel_for(None, false),
+    };
+    let mut selections = selections
+        .iter()
+        .map(|s| match s.split_once('@') {
+            Some((id, version)) => Version::parse(version)
+                .map(|v| (id.to_string(), Some(v)))
+                .map_err(|e| format!("Invalid version in {s:?}: {e}")),
+            None => Ok((s.clone(), None)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if selections.is_empty() {
+        selections = manifest
+            .products()
+            .iter()
+            .map(|p| (p.id().clone(), None))
+            .collect();
+    }
+
+    let archive = ArchiveFormat::from_path(output);
+    let tempdir;
+    let root = if archive.is_some() {
+        tempdir = tempfile::tempdir()
+            .map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+        tempdir.path().to_path_buf()
+    } else {
+        fs::create_dir_all(output).map_err(|e| format!("Failed to create bundle: {e}"))?;
+        output.to_path_buf()
+    };
+
+    let mut products = vec![];
+    for (id, version) in selections {
+        let prod = manifest
+            .products()
+            .iter()
+            .find(|p| *p.id() == id)
+            .ok_or(format!("No product with ID {id}"))?;
+        let version = match version {
+            Some(version) => version,
+            None => prod
+                .latest_version(&channel, None)
+                .ok_or(format!(
+                    "{id} has no versions in the {} channel",
+                    channel.name()
+                ))?
+                .version()
+                .clone(),
+        };
+        let prod_version = prod
+            .versions()
+            .iter()
+            .find(|v| *v.version() == version)
+            .ok_or(format!("{id} has no version {version}"))?;
+
+        let mut downloads = ProductDownloads::default();
+        for &platform in platforms {
+            let Some(download) = prod_version.downloads().for_platform(platform) else {
+                tracing::warn!("{id} v{version} is not available for {platform}, skipping");
+                continue;
+            };
+            tracing::info!("Bundling {id} v{version} for {platform}");
+            let relative_dir = format!("artifacts/{id}/{version}/{platform}");
+            let dir = root.join(&relative_dir);
+            fs::create_dir_all(&dir).map_err(|e| format!("Failed to create bundle: {e}"))?;
+            let downloaded = artifact::download(&download, &dir).await?;
+
+            let file_name = download
+                .url()
+                .split(['?', '#'])
+                .next()
+                .and_then(|u| u.rsplit('/').next())
+                .filter(|n| !n.is_empty())
+                .unwrap_or("data")
+                .to_string();
+            let path = dir.join(&file_name);
+            fs::rename(&downloaded.path, &path)
+                .map_err(|e| format!("Failed to write bundle: {e}"))?;
+
+            let mut spec = download.clone();
+            spec.set_url(format!("{relative_dir}/{file_name}"));
+            spec.set_mirrors(vec![]);
+            spec.set_size(Some(
+                fs::metadata(&path)
+                    .map_err(|e| format!("Failed to read bundled file: {e}"))?
+                    .len(),
+            ));
+            spec.set_sha256(Some(
+                inventory::hash_file(&path)
+                    .map_err(|e| format!("Failed to read bundled file: {e}"))?,
+            ));
+            downloads.set_for_platform(platform, Some(spec));
+        }
+
+        // Whoever the bundle is given to should get the version in it
+        let mut prod_version = prod_version.clone();
+        prod_version.set_downloads(downloads);
+        prod_version.set_rollout(None);
+        let mut prod = prod.clone();
+        prod.set_versions(vec![prod_version]);
+        products.push(prod);
+    }
+
+    let mut bundle_manifest = manifest.clone();
+    bundle_manifest.set_products(products);
+    serde_json::to_writer_pretty(
+        BufWriter::new(
+            fs::File::create(root.join(BUNDLE_MANIFEST))
+                .map_err(|e| format!("Failed to write bundle manifest: {e}"))?,
+        ),
+        &bundle_manifest,
+    )
+    .map_err(|e| format!("Failed to write bundle manifest: {e}"))?;
+
+    if let Some(format) = archive {
+        tracing::info!("Packing bundle into {output:?}");
+        pack(&root, output, format).map_err(|e| format!("Failed to pack bundle: {e}"))?;
+    }
+    tracing::info!("Bundle written to {output:?}");
+    Ok(())
+}
+
+fn pack(root: &Path, output: &Path, format: ArchiveFormat) -> io::Result<()> {
+    let files = inventory::list(root, &[])?;
+    let writer = BufWriter::new(fs::File::create(output)?);
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(writer);
+            for relative in files {
+                zip.start_file(relative.as_str(), SimpleFileOptions::default())?;
+                io::copy(&mut fs::File::open(root.join(&relative))?, &mut zip)?;
+            }
+            zip.finish()?;
+        }
+        ArchiveFormat::GzippedTarball => {
+            let mut tar = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+            for relative in files {
+                tar.append_path_with_name(root.join(&relative), &relative)?;
+            }
+            tar.into_inner()?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn bundled_versions_skip_their_rollout() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifact = dir.path().join("p.zip");
+        fs::write(&artifact, "zip").unwrap();
+        let url = Url::from_file_path(&artifact).unwrap();
+        let download = json!({ "url": url.as_str(), "strategy": "ZipFile" });
+        let manifest = json!({
+            "products": [{
+                "id": "p",
+                "name": "P",
+                "description": "",
+                "install_directory": "p",
+                "removals": [],
+                "versions": [{
+                    "version": "1.0.0",
+                    "rollout": { "percentage": 10, "start": "2999-01-01T00:00:00Z" },
+                    "downloads": {
+                        "windows": download,
+                        "mac": download,
+                        "mac-intel": download,
+                        "linux": download,
+                    },
+                }],
+            }],
+        });
+        let manifest = Manifest::parse(manifest.to_string().as_bytes()).unwrap();
+        let output = dir.path().join("bundle");
+        let platforms = [Platform::current().unwrap()];
+
+        tauri::async_runtime::block_on(export(&manifest, &output, &[], &platforms, None)).unwrap();
+
+        let bundled = Bundle::open(&output).unwrap().manifest().unwrap();
+        let prod = &bundled.products()[0];
+        assert!(prod.versions()[0].rollout().is_none());
+        let best = prod.best_version_for_this_system(&bundled.channel_for(None, false), None);
+        assert_eq!(best.map(|(v, _)| v), Some(Version::new(1, 0, 0)));
+    }
+}
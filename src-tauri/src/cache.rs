@@ -0,0 +1,158 @@
+use std::io::{BufReader, BufWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use getset::Getters;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::http;
+use crate::manifest::{Manifest, ManifestError};
+
+/// The last manifest fetched successfully, kept so the installer can work
+/// offline and make conditional requests.
+#[derive(Clone, Serialize, Deserialize, Getters)]
+#[getset(get = "pub")]
+pub struct ManifestCache {
+    /// The URL the manifest was fetched from
+    url: String,
+    /// The `ETag` the manifest was served with
+    etag: Option<String>,
+    /// The `Last-Modified` date the manifest was served with
+    last_modified: Option<String>,
+    /// When the manifest was last fetched or confirmed unchanged, in seconds since the Unix epoch
+    fetched_at: u64,
+    /// The manifest as it was served. It is read again each time it is
+    /// used, as what can be read from it depends on the installer version.
+    #[getset(skip)]
+    body: String,
+}
+
+impl ManifestCache {
+    pub fn new(
+        url: String,
+        body: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    ) -> Self {
+        Self {
+            url,
+            etag,
+            last_modified,
+            fetched_at: now(),
+            body,
+        }
+    }
+
+    /// Read the cached manifest.
+    pub fn manifest(&self) -> Result<Manifest, ManifestError> {
+        Manifest::parse(self.body.as_bytes())
+    }
+
+    /// Load the cached manifest for `url`, if there is a usable one on disk.
+    pub fn load(url: &str) -> Option<Self> {
+        let f = std::fs::File::open(super::local_manifest_cache_file()).ok()?;
+        match serde_json::from_reader::<_, Self>(BufReader::new(f)) {
+            Ok(cache) if cache.url != url => {
+                tracing::debug!("Ignoring manifest cache for a different URL");
+                None
+            }
+            Ok(cache) => match cache.manifest() {
+                Ok(_) => Some(cache),
+                Err(e) => {
+                    tracing::debug!("Ignoring manifest cache that can't be used: {e}");
+                    None
+                }
+            },
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable manifest cache: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = super::local_manifest_cache_file();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        serde_json::to_writer(BufWriter::new(std::fs::File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Record that the server has confirmed the cached manifest is current.
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+}
+
+/// Fetch the manifest from `url`, keeping the cache up to date and only
+/// downloading it again if it has changed. If the manifest can't be reached
+/// or what is served can't be read, the cached copy (if any) is returned
+/// along with `true` to say we are working from a stale copy.
+pub async fn fetch(url: &str) -> Result<(Option<ManifestCache>, bool), ManifestError> {
+    let mut cache = ManifestCache::load(url);
+    let mut request = http::client().get(url);
+    if let Some(cache) = &cache {
+        if let Some(etag) = cache.etag() {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = cache.last_modified() {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let res = request.send().await.and_then(|r| r.error_for_status());
+    tracing::trace!("Manifest fetch response: {res:?}");
+    match res {
+        Ok(res) if res.status() == StatusCode::NOT_MODIFIED => {
+            tracing::debug!("Manifest unchanged since last fetch.");
+            if let Some(cache) = cache.as_mut() {
+                cache.touch();
+                if let Err(e) = cache.save() {
+                    tracing::warn!("Failed to update manifest cache: {e}");
+                }
+            }
+            Ok((cache, false))
+        }
+        Ok(res) => {
+            let header = |name| {
+                res.headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            };
+            let etag = header(ETAG);
+            let last_modified = header(LAST_MODIFIED);
+            let bytes = res
+                .bytes()
+                .await
+                .map_err(|e| ManifestError::Invalid(format!("Failed to read manifest: {e}")))?;
+            match Manifest::parse(&bytes) {
+                Ok(_) => (),
+                Err(ManifestError::Invalid(e)) if cache.is_some() => {
+                    tracing::error!("The fetched manifest is invalid, using the cached one: {e}");
+                    return Ok((cache, true));
+                }
+                Err(e) => return Err(e),
+            }
+            tracing::debug!("Fetched manifest.");
+            let body = String::from_utf8_lossy(&bytes).to_string();
+            let fresh = ManifestCache::new(url.to_string(), body, etag, last_modified);
+            if let Err(e) = fresh.save() {
+                tracing::warn!("Failed to cache manifest: {e}");
+            }
+            Ok((Some(fresh), false))
+        }
+        Err(e) => {
+            tracing::info!("Failed to fetch manifest: {e}");
+            Ok((cache, true))
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,312 @@
+use std::env;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use semver::Version;
+
+use crate::apply;
+use crate::authoring::{self, ArtifactArg, Release};
+use crate::bundle::{self, Bundle};
+use crate::install::Install;
+use crate::manifest::{self, Platform};
+use crate::profile::Profile;
+use crate::rollout;
+use crate::source::ManifestSource;
+use crate::validate::{self, Report};
+
+/// An installer utility for managing Angelsuite tools.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Install from an offline bundle (a directory, .zip or .tar.gz file) instead of the online product list
+    #[arg(long, global = true, value_name = "PATH")]
+    pub bundle: Option<PathBuf>,
+    /// Where to get the product list from: a URL, a file or a directory containing manifest.json.
+    /// Local files are watched and reloaded when they change
+    #[arg(long, global = true, value_name = "SOURCE", conflicts_with = "bundle")]
+    pub manifest: Option<String>,
+    /// Keep installed products, settings, the cache and logs in this directory instead of the usual places.
+    /// Can also be set with ANGELSUITE_ROOT
+    #[arg(long, global = true, value_name = "DIR")]
+    pub root: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// Commands are parsed once, so their size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Install, update, pin and remove products until this machine matches a profile, then print a JSON report of what was done.
+    /// Exits with an error if the machine doesn't match afterwards
+    Apply {
+        /// The profile to match, as exported from the installer
+        file: PathBuf,
+        /// Report what would be done without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write an offline bundle of products, for installing on machines without internet access
+    ExportBundle {
+        /// Where to write the bundle. Paths ending in .zip or .tar.gz are packed into an archive, anything else is a directory
+        output: PathBuf,
+        /// A product to include, as `id` or `id@version`. Can be given more than once. Defaults to every product at its latest version
+        #[arg(short, long = "product", value_name = "ID[@VERSION]")]
+        products: Vec<String>,
+        /// A platform to include (windows, mac, mac-intel or linux). Can be given more than once. Defaults to this system
+        #[arg(long = "platform", value_name = "PLATFORM")]
+        platforms: Vec<Platform>,
+        /// The release channel to take versions from when no version is given. Defaults to the most stable channel
+        #[arg(long, value_name = "ID")]
+        channel: Option<String>,
+    },
+    /// Edit a manifest file
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommand,
+    },
+    /// Print the JSON Schema for manifests, for editor completion and validation
+    Schema {
+        /// Write the schema to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check the manifest (from --manifest, --bundle or the usual place) for mistakes, exiting with an error if any are found
+    ValidateManifest {
+        /// Check every download URL and mirror can be reached
+        #[arg(long)]
+        check_urls: bool,
+        /// Download artifacts to check their size, checksum and executable. Implies --check-urls
+        #[arg(long)]
+        download: bool,
+        /// Download every version, not just the latest
+        #[arg(long, requires = "download")]
+        all_versions: bool,
+        /// The manifest will be published at a URL, so its download URLs can't be relative.
+        /// Implied when the manifest is read from a URL
+        #[arg(long)]
+        remote: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ManifestCommand {
+    /// Add a release of a product, made from its built artifacts
+    AddVersion {
+        /// The manifest file to add the version to
+        manifest: PathBuf,
+        /// The ID of the product
+        id: String,
+        /// The new version
+        version: Version,
+        /// A built artifact, as `platform=path` or `platform=url`. Can be given once per platform
+        #[arg(
+            short,
+            long = "artifact",
+            value_name = "PLATFORM=PATH|URL",
+            required = true
+        )]
+        artifacts: Vec<ArtifactArg>,
+        /// The URL local artifacts will be published under
+        #[arg(long, value_name = "URL", required_unless_present = "relative")]
+        base_url: Option<String>,
+        /// Refer to local artifacts by their path from the manifest, which must be next to them.
+        /// This only works for manifests read from a file or bundle, not from a URL
+        #[arg(long, conflicts_with = "base_url")]
+        relative: bool,
+        /// The program to start, relative to the install directory, if it can't be worked out
+        #[arg(long, value_name = "PATH")]
+        executable: Option<String>,
+        /// The product code of MSI artifacts
+        #[arg(long, value_name = "GUID")]
+        msi_product_code: Option<String>,
+        /// Release notes for the version, as Markdown or a URL
+        #[arg(long, value_name = "NOTES")]
+        release_notes: Option<String>,
+        /// When the version was released, as an ISO 8601 date. Defaults to today
+        #[arg(long, value_name = "DATE")]
+        released_at: Option<String>,
+        /// The oldest installer that can install this version
+        #[arg(long, value_name = "VERSION")]
+        requires_installer: Option<Version>,
+        /// Replace the version if the manifest already has it
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+impl Cli {
+    /// Determine where the manifest should come from. In order of preference
+    /// this is `--bundle`, `--manifest`, `ANGELSUITE_MANIFEST`, then the
+    /// `manifest` setting in the install data.
+    pub fn source(&self) -> Result<ManifestSource, String> {
+        if let Some(path) = &self.bundle {
+            return Ok(ManifestSource::Bundle(Bundle::open(path)?));
+        }
+        let configured = self
+            .manifest
+            .clone()
+            .or_else(|| {
+                env::var("ANGELSUITE_MANIFEST")
+                    .ok()
+                    .filter(|v| !v.is_empty())
+            })
+            .or_else(|| Install::load().and_then(|i| i.manifest().clone()));
+        match configured {
+            Some(value) => ManifestSource::parse(&value),
+            None => Ok(ManifestSource::default()),
+        }
+    }
+}
+
+/// Release builds on Windows don't get a console, so borrow the one we were
+/// started from (if any) so command line output can be seen.
+pub fn attach_console() {
+    #[cfg(windows)]
+    // SAFETY: this has no preconditions and failure (no parent console) is harmless
+    unsafe {
+        use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Run a command line subcommand, returning the process exit code.
+pub fn execute(command: Command, source: &ManifestSource) -> i32 {
+    let result = tauri::async_runtime::block_on(async {
+        match command {
+            Command::Apply { file, dry_run } => {
+                let profile = Profile::load(&file)?;
+                let manifest = source
+                    .load(false)
+                    .await?
+                    .manifest
+                    .ok_or(super::NO_MANIFEST_ERROR.to_string())?;
+                let mut install = match Install::load() {
+                    Some(install) => install,
+                    None if super::local_install_file().exists() => {
+                        return Err(format!(
+                            "{} is invalid",
+                            super::local_install_file().display()
+                        ))
+                    }
+                    None => Install::default(),
+                };
+                install.follow_portable_dir();
+                rollout::follow_setting(&install);
+                let report = apply::apply(&profile, &manifest, &mut install, dry_run).await?;
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+                if report.succeeded() {
+                    Ok(())
+                } else {
+                    Err("This machine doesn't match the profile.".to_string())
+                }
+            }
+            Command::ExportBundle {
+                output,
+                products,
+                mut platforms,
+                channel,
+            } => {
+                let manifest = source
+                    .load(false)
+                    .await?
+                    .manifest
+                    .ok_or(super::NO_MANIFEST_ERROR.to_string())?;
+                if platforms.is_empty() {
+                    platforms.extend(Platform::current());
+                }
+                bundle::export(
+                    &manifest,
+                    &output,
+                    &products,
+                    &platforms,
+                    channel.as_deref(),
+                )
+                .await
+            }
+            Command::Manifest {
+                command:
+                    ManifestCommand::AddVersion {
+                        manifest,
+                        id,
+                        version,
+                        artifacts,
+                        base_url,
+                        relative,
+                        executable,
+                        msi_product_code,
+                        release_notes,
+                        released_at,
+                        requires_installer,
+                        replace,
+                    },
+            } => {
+                let release = Release {
+                    id,
+                    version,
+                    release_notes,
+                    released_at,
+                    requires_installer,
+                    executable,
+                    msi_product_code,
+                    base_url,
+                    relative,
+                    replace,
+                };
+                authoring::add_version(&manifest, &release, &artifacts).await
+            }
+            Command::Schema { output } => {
+                let schema = serde_json::to_string_pretty(&manifest::schema())
+                    .map_err(|e| format!("Failed to write schema: {e}"))?;
+                match output {
+                    Some(path) => std::fs::write(&path, schema + "\n")
+                        .map_err(|e| format!("Failed to write schema: {e}")),
+                    None => {
+                        println!("{schema}");
+                        Ok(())
+                    }
+                }
+            }
+            Command::ValidateManifest {
+                check_urls,
+                download,
+                all_versions,
+                remote,
+            } => {
+                let bytes = source.read_raw().await?;
+                let mut report = Report::default();
+                if let Some(mut manifest) = validate::check_syntax(&mut report, &bytes) {
+                    validate::check_semantics(&mut report, &manifest);
+                    if remote || source.is_online() {
+                        validate::check_absolute_urls(&mut report, &manifest);
+                    }
+                    if check_urls || download {
+                        if let Some(dir) = source.base_dir() {
+                            manifest.resolve_relative_urls(&dir);
+                        }
+                        validate::check_downloads(&mut report, &manifest, download, all_versions)
+                            .await;
+                    }
+                }
+                for problem in report.problems() {
+                    println!("{problem}");
+                }
+                match report.problems().len() {
+                    0 => {
+                        println!("No problems found.");
+                        Ok(())
+                    }
+                    1 => Err("1 problem found.".to_string()),
+                    n => Err(format!("{n} problems found.")),
+                }
+            }
+        }
+    });
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
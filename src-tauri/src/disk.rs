@@ -0,0 +1,70 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Determine the free space on the volume holding `path`. If `path` doesn't
+/// exist yet, the nearest existing parent is used instead.
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    let mut path = path;
+    while !path.exists() {
+        match path.parent() {
+            Some(parent) => path = parent,
+            None => break,
+        }
+    }
+    fs4::available_space(path)
+}
+
+/// Fail with a readable message if the volume holding `path` doesn't have at
+/// least `required` bytes free.
+pub fn ensure_free_space(path: &Path, required: u64) -> Result<(), String> {
+    let available = match free_space(path) {
+        Ok(available) => available,
+        Err(e) => {
+            // If we can't tell, let the install have a go anyway
+            tracing::warn!("Couldn't determine free space for {path:?}: {e}");
+            return Ok(());
+        }
+    };
+    tracing::debug!("{path:?} requires {required} bytes, {available} bytes available");
+    if available < required {
+        return Err(format!(
+            "Not enough free disk space in {}: {} is needed but only {} is available.",
+            path.display(),
+            format_size(required),
+            format_size(available),
+        ));
+    }
+    Ok(())
+}
+
+/// Calculate the total size of all files below `path`.
+pub fn directory_size(path: &Path) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += directory_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Format a number of bytes for humans.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+    format!("{size:.1} {unit}")
+}
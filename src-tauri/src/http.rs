@@ -0,0 +1,86 @@
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, ClientBuilder, NoProxy, Proxy};
+
+/// How long to wait for a connection unless `ANGELSUITE_CONNECT_TIMEOUT` says otherwise
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait between reads unless `ANGELSUITE_READ_TIMEOUT` says otherwise
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Get the HTTP client that all installer traffic should go through.
+pub fn client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            configure(Client::builder())
+                .build()
+                .expect("failed to build HTTP client")
+        })
+        .clone()
+}
+
+/// Apply the network settings from the environment to a client builder:
+///
+/// - `ANGELSUITE_PROXY` sends all traffic through the given proxy URL,
+///   bypassing it for hosts in `ANGELSUITE_NO_PROXY` (or `NO_PROXY`). Without
+///   it, the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`
+///   variables are honoured.
+/// - `ANGELSUITE_CA_BUNDLE` is the path to a PEM file of extra root
+///   certificates to trust, for networks that intercept TLS.
+/// - `ANGELSUITE_CONNECT_TIMEOUT` and `ANGELSUITE_READ_TIMEOUT` are in seconds.
+pub fn configure(mut builder: ClientBuilder) -> ClientBuilder {
+    if let Some(proxy_url) = env_string("ANGELSUITE_PROXY") {
+        match Proxy::all(&proxy_url) {
+            Ok(proxy) => {
+                tracing::info!("Using proxy {proxy_url}");
+                let no_proxy = env_string("ANGELSUITE_NO_PROXY")
+                    .and_then(|v| NoProxy::from_string(&v))
+                    .or_else(NoProxy::from_env);
+                builder = builder.proxy(proxy.no_proxy(no_proxy));
+            }
+            Err(e) => tracing::warn!("Ignoring invalid proxy {proxy_url}: {e}"),
+        }
+    }
+
+    if let Some(bundle) = env_string("ANGELSUITE_CA_BUNDLE") {
+        match fs::read(&bundle)
+            .map_err(|e| e.to_string())
+            .and_then(|pem| Certificate::from_pem_bundle(&pem).map_err(|e| e.to_string()))
+        {
+            Ok(certificates) => {
+                tracing::info!(
+                    "Trusting {} extra root certificates from {bundle}",
+                    certificates.len()
+                );
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load CA bundle {bundle}: {e}"),
+        }
+    }
+
+    builder
+        .connect_timeout(
+            env_seconds("ANGELSUITE_CONNECT_TIMEOUT").unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+        )
+        .read_timeout(env_seconds("ANGELSUITE_READ_TIMEOUT").unwrap_or(DEFAULT_READ_TIMEOUT))
+}
+
+fn env_string(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+fn env_seconds(key: &str) -> Option<Duration> {
+    let value = env_string(key)?;
+    match value.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid {key} {value:?}: {e}");
+            None
+        }
+    }
+}
@@ -1,15 +1,36 @@
 use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
 use getset::{Getters, Setters};
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Serialize, Deserialize, Getters, Default)]
+use crate::inventory::Inventory;
+
+#[derive(Clone, Serialize, Deserialize, Getters, Setters, Default)]
 #[getset(get = "pub")]
 pub struct Install {
     products: BTreeMap<String, InstalledProduct>,
+    /// Where to get the manifest from instead of the usual URL: a URL, a file or a directory.
+    manifest: Option<String>,
+    /// Get new versions before their rollout reaches this machine.
+    #[serde(default)]
+    #[getset(set = "pub")]
+    early_access: bool,
+    /// The directory to install products under instead of the usual one.
+    #[getset(set = "pub")]
+    install_root: Option<String>,
+    /// Where the installer was when this was saved in portable mode.
+    portable_dir: Option<String>,
 }
 
 impl Install {
+    /// Read the install data from disk, if there is any.
+    pub fn load() -> Option<Self> {
+        let f = std::fs::File::open(super::local_install_file()).ok()?;
+        serde_json::from_reader(std::io::BufReader::new(f)).ok()
+    }
+
     pub fn save(&self) -> std::io::Result<()> {
         serde_json::to_writer(
             std::io::BufWriter::new(std::fs::File::create(super::local_install_file())?),
@@ -19,6 +40,37 @@ impl Install {
         Ok(())
     }
 
+    /// Get the directory a product is installed under: the one chosen for
+    /// it, the one chosen for every product, or the usual install directory.
+    pub fn install_root_for(&self, id: &str) -> PathBuf {
+        self.products
+            .get(id)
+            .and_then(|p| p.install_root.as_ref())
+            .or(self.install_root.as_ref())
+            .map(PathBuf::from)
+            .unwrap_or_else(super::local_install_dir)
+    }
+
+    /// In portable mode, move recorded paths along with the installer if it
+    /// has moved since they were saved, such as to a new drive letter.
+    pub fn follow_portable_dir(&mut self) {
+        let Some(dir) = super::portable_dir() else {
+            return;
+        };
+        if let Some(old) = self.portable_dir.as_ref().map(PathBuf::from) {
+            if old != dir {
+                tracing::info!("The installer has moved from {old:?} to {dir:?}");
+                if let Some(root) = self.install_root.as_mut() {
+                    rebase_path(root, &old, &dir);
+                }
+                for prod in self.products.values_mut() {
+                    prod.rebase(&old, &dir);
+                }
+            }
+        }
+        self.portable_dir = Some(dir.to_string_lossy().to_string());
+    }
+
     pub fn get_mut_product_or_default(&mut self, id: String) -> &mut InstalledProduct {
         if !self.products.contains_key(&id) {
             self.products
@@ -41,12 +93,117 @@ pub struct InstalledProduct {
     icon: Option<String>,
     /// The installed version, if the product is installed.
     version: Option<String>,
+    /// The URL the installed version was downloaded from, if the product is installed.
+    installed_from: Option<String>,
+    /// The size of the installation on disk in bytes, if the product is installed.
+    installed_size: Option<u64>,
+    /// The directory the product is installed in, if the product is installed.
+    install_directory: Option<String>,
+    /// The directory chosen to install this product under, if not the one for every product.
+    install_root: Option<String>,
+    /// The directory the product was last removed from, as anything left there isn't a copy to adopt.
+    removed_from: Option<String>,
+    /// The paths, relative to the install directory, that hold user data.
+    #[serde(default)]
+    user_data: Vec<String>,
     /// The path to the working directory of this product, if it can be started.
     execute_working_directory: Option<String>,
     /// The path to the executable to start this product, if it can be started.
     main_executable: Option<String>,
-    /// Should this product use prerelease versions?
+    /// Should this product use prerelease versions? Only read to pick a channel
+    /// for products installed before there were channels.
     use_prerelease: bool,
+    /// The ID of the release channel this product follows, if one has been chosen.
+    channel: Option<String>,
+    /// The range of versions this product is held within, if it is pinned.
+    pin: Option<VersionReq>,
     /// Should this product remove an MSI on uninstal?
     msi_product_code: Option<String>,
+    /// The files put down by the installer, relative to the install directory.
+    #[serde(default)]
+    files: Inventory,
+}
+
+impl InstalledProduct {
+    /// Update recorded paths below `from` to be below `to` instead, after the
+    /// files have moved. Paths elsewhere, such as those from
+    /// `executable_absolute`, stay put.
+    pub fn rebase(&mut self, from: &Path, to: &Path) {
+        for path in [
+            &mut self.install_directory,
+            &mut self.install_root,
+            &mut self.removed_from,
+            &mut self.main_executable,
+            &mut self.execute_working_directory,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            rebase_path(path, from, to);
+        }
+    }
+}
+
+/// Update `path` to be below `to` instead of `from`, if it is below `from`.
+fn rebase_path(path: &mut String, from: &Path, to: &Path) {
+    if let Ok(relative) = Path::new(path).strip_prefix(from) {
+        *path = if relative.as_os_str().is_empty() {
+            to.to_string_lossy().to_string()
+        } else {
+            to.join(relative).to_string_lossy().to_string()
+        };
+    }
+}
+
+/// A file put down by the installer.
+#[derive(Clone, Debug, Serialize, Deserialize, Getters, PartialEq, Eq)]
+#[getset(get = "pub")]
+pub struct InstalledFile {
+    /// The size of the file in bytes
+    size: u64,
+    /// The hex encoded SHA-256 hash of the file
+    sha256: String,
+}
+
+impl InstalledFile {
+    pub fn new(size: u64, sha256: String) -> Self {
+        Self { size, sha256 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebased(path: &str, from: &str, to: &str) -> String {
+        let mut path = path.to_string();
+        rebase_path(&mut path, Path::new(from), Path::new(to));
+        path
+    }
+
+    #[test]
+    fn rebase_moves_paths_below_from() {
+        assert_eq!(
+            PathBuf::from(rebased("/old/root/app/app.exe", "/old/root", "/new")),
+            Path::new("/new").join("app").join("app.exe")
+        );
+    }
+
+    #[test]
+    fn rebase_moves_from_itself() {
+        assert_eq!(rebased("/old/root", "/old/root", "/new"), "/new");
+    }
+
+    #[test]
+    fn rebase_leaves_other_paths() {
+        assert_eq!(
+            rebased("/elsewhere/app", "/old/root", "/new"),
+            "/elsewhere/app"
+        );
+        // Only whole path components count
+        assert_eq!(
+            rebased("/old/rootless", "/old/root", "/new"),
+            "/old/rootless"
+        );
+    }
 }
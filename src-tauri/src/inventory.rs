@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::install::InstalledFile;
+
+/// The files installed for a product, keyed by their path relative to the
+/// install directory, always using `/` as the separator.
+pub type Inventory = BTreeMap<String, InstalledFile>;
+
+/// Calculate the hex encoded SHA-256 hash of a file.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Record every file below `dir`, skipping anything under one of the
+/// `excluded` relative paths.
+pub fn build(dir: &Path, excluded: &[String]) -> io::Result<Inventory> {
+    let mut inventory = Inventory::new();
+    for relative in list(dir, excluded)? {
+        let path = dir.join(&relative);
+        let size = fs::metadata(&path)?.len();
+        inventory.insert(relative, InstalledFile::new(size, hash_file(&path)?));
+    }
+    Ok(inventory)
+}
+
+/// List every file below `dir` by its relative path, skipping anything under
+/// one of the `excluded` relative paths.
+pub fn list(dir: &Path, excluded: &[String]) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    list_into(&mut files, dir, dir, excluded)?;
+    Ok(files)
+}
+
+fn list_into(
+    files: &mut Vec<String>,
+    root: &Path,
+    dir: &Path,
+    excluded: &[String],
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let relative = relative_key(root, &path);
+        if is_excluded(&relative, excluded) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            list_into(files, root, &path, excluded)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+fn relative_key(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_excluded(relative: &str, excluded: &[String]) -> bool {
+    excluded.iter().any(|ex| {
+        let ex = ex.replace('\\', "/");
+        let ex = ex.trim_end_matches('/');
+        relative == ex || relative.starts_with(&format!("{ex}/"))
+    })
+}
+
+/// Has the installed copy of `file` been changed since it was installed?
+/// Missing files are not considered modified.
+pub fn is_modified(install_directory: &Path, relative: &str, file: &InstalledFile) -> bool {
+    let path = install_directory.join(relative);
+    match fs::metadata(&path) {
+        Ok(meta) if meta.len() != *file.size() => true,
+        Ok(_) => hash_file(&path).is_ok_and(|hash| hash != *file.sha256()),
+        Err(_) => false,
+    }
+}
+
+/// The result of checking an installation against its inventory.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Verification {
+    /// Installed files that no longer exist
+    pub missing: Vec<String>,
+    /// Installed files that have changed since they were installed
+    pub modified: Vec<String>,
+    /// Files in the install directory that weren't installed by us
+    pub extra: Vec<String>,
+}
+
+impl Verification {
+    /// Does this installation need repairing?
+    pub fn is_damaged(&self) -> bool {
+        !self.missing.is_empty() || !self.modified.is_empty()
+    }
+}
+
+/// Check the files in `install_directory` against `inventory`. Anything under
+/// one of the `excluded` relative paths is ignored.
+pub fn verify(
+    install_directory: &Path,
+    inventory: &Inventory,
+    excluded: &[String],
+) -> io::Result<Verification> {
+    let mut verification = Verification::default();
+    for (relative, file) in inventory {
+        if !install_directory.join(relative).exists() {
+            verification.missing.push(relative.clone());
+        } else if is_modified(install_directory, relative, file) {
+            verification.modified.push(relative.clone());
+        }
+    }
+    if install_directory.exists() {
+        verification.extra = list(install_directory, excluded)?
+            .into_iter()
+            .filter(|relative| !inventory.contains_key(relative))
+            .collect();
+    }
+    Ok(verification)
+}
+
+/// Restore every file in `install_directory` that is missing or differs from
+/// the freshly staged copy in `staging`, leaving everything else alone.
+/// Returns the inventory of the staged files.
+pub fn repair(
+    staging: &Path,
+    install_directory: &Path,
+    excluded: &[String],
+) -> io::Result<Inventory> {
+    let files = build(staging, excluded)?;
+    for (relative, file) in &files {
+        let target = install_directory.join(relative);
+        if target.exists() && !is_modified(install_directory, relative, file) {
+            continue;
+        }
+        tracing::info!("Restoring {target:?}");
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let _ = fs::remove_file(&target);
+        fs::copy(staging.join(relative), &target)?;
+    }
+    Ok(files)
+}
+
+/// Move from the `previous` inventory to the `next` one by copying the files
+/// from `staging` into `install_directory` and removing files that are no
+/// longer shipped. Files the user has modified are never silently lost: if the
+/// new version replaces them the user's copy is kept alongside with a `.bak`
+/// extension, and if it no longer ships them they are left in place.
+pub fn apply(
+    staging: &Path,
+    install_directory: &Path,
+    previous: &Inventory,
+    next: &Inventory,
+) -> io::Result<()> {
+    for (relative, file) in previous {
+        if !is_modified(install_directory, relative, file) {
+            continue;
+        }
+        let path = install_directory.join(relative);
+        if next.contains_key(relative) {
+            let mut backup = path.clone().into_os_string();
+            backup.push(".bak");
+            tracing::warn!("{path:?} was modified, keeping a copy at {backup:?}");
+            fs::copy(&path, backup)?;
+        } else {
+            tracing::warn!("{path:?} was modified and is no longer shipped, leaving it in place");
+        }
+    }
+
+    let stale: Vec<_> = previous
+        .iter()
+        .filter(|(relative, _)| !next.contains_key(*relative))
+        .filter(|(relative, file)| !is_modified(install_directory, relative, file))
+        .map(|(relative, _)| relative.clone())
+        .collect();
+    remove(install_directory, stale.iter())?;
+
+    for relative in next.keys() {
+        let source = staging.join(relative);
+        let target = install_directory.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replace rather than overwrite in case the old file is read-only
+        let _ = fs::remove_file(&target);
+        fs::copy(&source, &target)?;
+    }
+    Ok(())
+}
+
+/// Remove the listed files from `install_directory`, along with any
+/// directories left empty by doing so.
+pub fn remove<'a, I>(install_directory: &Path, files: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    for relative in files {
+        let path = install_directory.join(relative);
+        tracing::debug!("Removing file {path:?}");
+        match fs::remove_file(&path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(e),
+        }
+        let mut parent = path.parent();
+        while let Some(dir) = parent {
+            if !dir.starts_with(install_directory) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+            parent = dir.parent();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, relative: &str, contents: &str) {
+        let path = dir.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read(dir: &Path, relative: &str) -> String {
+        fs::read_to_string(dir.join(relative)).unwrap()
+    }
+
+    #[test]
+    fn apply_keeps_modified_files() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        let installed = tempfile::tempdir().unwrap();
+        for file in [
+            "kept.txt",
+            "edited.txt",
+            "stale/file.txt",
+            "edited-stale.txt",
+        ] {
+            write(old.path(), file, "old");
+            write(installed.path(), file, "old");
+        }
+        write(new.path(), "kept.txt", "new");
+        write(new.path(), "edited.txt", "new");
+        write(installed.path(), "edited.txt", "mine");
+        write(installed.path(), "edited-stale.txt", "mine");
+        let previous = build(old.path(), &[]).unwrap();
+        let next = build(new.path(), &[]).unwrap();
+
+        apply(new.path(), installed.path(), &previous, &next).unwrap();
+
+        let dir = installed.path();
+        assert_eq!(read(dir, "kept.txt"), "new");
+        assert_eq!(read(dir, "edited.txt"), "new");
+        assert_eq!(read(dir, "edited.txt.bak"), "mine");
+        assert_eq!(read(dir, "edited-stale.txt"), "mine");
+        assert!(!dir.join("stale").exists());
+    }
+
+    #[test]
+    fn verify_reports_damage() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["intact.txt", "missing.txt", "modified.txt"] {
+            write(dir.path(), file, "shipped");
+        }
+        let inventory = build(dir.path(), &[]).unwrap();
+        fs::remove_file(dir.path().join("missing.txt")).unwrap();
+        write(dir.path(), "modified.txt", "changed");
+        write(dir.path(), "extra.txt", "extra");
+        write(dir.path(), "user/settings.txt", "user data");
+
+        let verification = verify(dir.path(), &inventory, &["user".to_string()]).unwrap();
+
+        assert_eq!(verification.missing, ["missing.txt"]);
+        assert_eq!(verification.modified, ["modified.txt"]);
+        assert_eq!(verification.extra, ["extra.txt"]);
+        assert!(verification.is_damaged());
+    }
+}
@@ -1,81 +1,58 @@
 use std::collections::HashMap;
 use std::env;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Mutex;
-use std::{fs, io::Write};
 
+use clap::Parser;
 use install::Install;
-use manifest::{DownloadStrategy, Manifest};
-use semver::Version;
+use manifest::{Channel, DownloadStrategy, Manifest, Product};
+use notify_debouncer_mini::notify::RecommendedWatcher;
+use notify_debouncer_mini::Debouncer;
+pub use paths::{
+    local_environment_file, local_install_dir, local_install_file, local_log_dir,
+    local_machine_id_file, local_manifest_cache_file, local_user_data_backup_dir, portable_dir,
+};
+use semver::{Version, VersionReq};
 use serde::Serialize;
-use tauri::{Manager, Runtime, Url};
+use source::ManifestSource;
+use tauri::{Emitter, Manager, Runtime, Url};
 use tauri_plugin_updater::UpdaterExt;
 
+mod adopt;
+mod apply;
+mod artifact;
+mod authoring;
+mod bundle;
+mod cache;
+mod cli;
+mod disk;
 mod gzip;
+mod http;
 mod install;
+mod inventory;
 mod manifest;
+mod paths;
+mod profile;
+mod rollout;
+mod source;
+mod userdata;
+mod validate;
 
-pub const MANIFEST_URL: &str = "https://gist.githubusercontent.com/lilopkins/a9a624367414e48f860f0fa0ef609c98/raw/manifest.json";
-
-#[cfg(target_os = "windows")]
-pub fn local_install_file() -> PathBuf {
-    local_install_dir().join("installer.json")
-}
-
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn local_install_file() -> PathBuf {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("angelsuite.json");
-    path
-}
-
-#[cfg(target_os = "windows")]
-pub fn local_environment_file() -> PathBuf {
-    local_install_dir().join(".env")
-}
-
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn local_environment_file() -> PathBuf {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("angelsuite.env");
-    path
-}
-
-#[cfg(target_os = "windows")]
-pub fn local_install_dir() -> PathBuf {
-    let exe_dir = std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-        .unwrap_or(PathBuf::from("."));
-
-    let test_file = exe_dir.join(".testio");
-    let writeable = std::fs::write(&test_file, []).is_ok();
-    let _ = std::fs::remove_file(&test_file);
-    tracing::debug!("Executable directory is writeable: {writeable}");
-
-    if writeable {
-        exe_dir
-    } else {
-        // default to an appdata folder, probably installed as admin
-        let mut base = dirs::data_local_dir().unwrap();
-        base.push("AngelSuite");
-        fs::create_dir_all(&base).unwrap();
-        base
-    }
-}
+/// The error shown when an action needs the manifest but it has never been fetched
+const NO_MANIFEST_ERROR: &str =
+    "The product list couldn't be downloaded. Check your connection and try again.";
 
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn local_install_dir() -> PathBuf {
-    let mut base = dirs::data_local_dir().unwrap();
-    base.push("angelsuite");
-    fs::create_dir_all(&base).unwrap();
-    base
-}
+pub const MANIFEST_URL: &str = "https://gist.githubusercontent.com/lilopkins/a9a624367414e48f860f0fa0ef609c98/raw/manifest.json";
 
 #[derive(Default)]
 struct AppData {
+    source: ManifestSource,
+    /// Watches a local manifest for changes, for as long as the app runs
+    _watcher: Option<Debouncer<RecommendedWatcher>>,
     manifest: Mutex<Option<Manifest>>,
     install_data: Mutex<Install>,
 }
@@ -84,6 +61,20 @@ struct AppData {
 struct ManifestLoadResult {
     can_auto_update: bool,
     installer_update_available: Option<String>,
+    /// Is the manifest unavailable, so we are working from the cache or installed products?
+    offline: bool,
+    /// When the manifest in use was last fetched, in seconds since the Unix epoch
+    manifest_fetched_at: Option<u64>,
+    /// Where the manifest came from, if it isn't the usual place
+    manifest_source: Option<String>,
+    /// The installer version the manifest needs, if this installer is too old to use it
+    required_installer: Option<String>,
+    /// The release channels products can follow, most stable first
+    channels: Vec<Channel>,
+    /// The directory new products are installed under
+    install_root: String,
+    /// Does this machine get new versions before their rollout reaches it?
+    early_access: bool,
     products: Vec<ManifestLoadResultProduct>,
 }
 
@@ -97,20 +88,49 @@ pub struct ManifestLoadResultProduct {
     pub icon: Option<String>,
     /// The local installed version of this product, if installed
     pub local_version: Option<String>,
-    /// The latest remote version of this product, excluding prereleases
-    pub remote_version: String,
-    /// The latest remote version of this product, including prereleases
-    pub remote_version_prerelease: String,
+    /// The version of this product in its channel to install on this system, if any has a build for it
+    pub remote_version: Option<String>,
+    /// The latest version of this product in its channel that has reached this machine, even if it has no build for this system
+    pub latest_version: Option<String>,
+    /// Is the remote version newer than the installed version?
+    pub update_available: bool,
+    /// Is the remote version older than the installed version, which should be moved off as it was withdrawn or is outside its pin?
+    pub downgrade_needed: bool,
+    /// The closest version to move to, if the installed version has been withdrawn
+    pub recommended_version: Option<String>,
     /// The description of this product
     pub description: String,
-    /// Is there a package available that matches this OS, excluding prereleases?
-    pub has_os_match_prerelease: bool,
-    /// Is there a package available that matches this OS, including prereleases?
-    pub has_os_match: bool,
     /// Can this installation be started?
     pub can_start: bool,
-    /// Prerelease enabled
-    pub allow_prerelease: bool,
+    /// The ID of the release channel this product follows, if known
+    pub channel: Option<String>,
+    /// The range of versions this product is held within, if it is pinned
+    pub pin: Option<String>,
+    /// The newest version in its channel the pin is holding this product back from
+    pub held_version: Option<String>,
+    /// Does this product keep user data that can survive removal?
+    pub has_user_data: bool,
+    /// The size of the installation on disk in bytes, if installed
+    pub installed_size: Option<u64>,
+    /// Has the installed version been withdrawn?
+    pub local_yanked: bool,
+    /// Why the installed version was withdrawn, if it has been
+    pub yank_reason: Option<String>,
+    /// The installer version needed for the newest version in its channel, if this installer is too old
+    pub required_installer: Option<String>,
+    /// The directory this product is installed in, or will be installed in
+    pub install_directory: Option<String>,
+    /// A copy of this product found where it would be installed, if it isn't recorded as installed
+    pub existing: Option<adopt::Existing>,
+}
+
+/// A summary of a completed install or repair
+#[derive(Serialize)]
+struct OperationSummary {
+    /// The version that was installed
+    version: String,
+    /// The URL the product was downloaded from, if it was downloaded
+    source: Option<String>,
 }
 
 #[tauri::command]
@@ -135,7 +155,7 @@ async fn load_manifest<R: Runtime>(
 
     let force_work_offline = env::var("ANGELSUITE_WORK_OFFLINE").is_ok_and(|v| !v.is_empty());
 
-    if !force_work_offline {
+    if !force_work_offline && state.source.is_online() {
         result.installer_update_available = if let Ok(u) = build_updater(&app) {
             if let Ok(Some(update)) = u.check().await {
                 tracing::info!("Installer update available ({})!", update.version);
@@ -149,7 +169,7 @@ async fn load_manifest<R: Runtime>(
     }
 
     // Check if `installer.json` exists. If not, create it.
-    let install_data = if let Ok(f) = fs::File::open(local_install_file()) {
+    let mut install_data = if let Ok(f) = fs::File::open(local_install_file()) {
         let i: Install =
             serde_json::from_reader(BufReader::new(f)).expect("installer.json is invalid on disk");
         i
@@ -163,17 +183,22 @@ async fn load_manifest<R: Runtime>(
         ))
         .expect("installer.json is invalid on disk")
     };
+    install_data.follow_portable_dir();
+    rollout::follow_setting(&install_data);
+    result.early_access = rollout::early_access();
 
-    let res = if force_work_offline {
-        None
-    } else {
-        let res = reqwest::get(MANIFEST_URL).await;
-        tracing::trace!("Manifest fetch response: {res:?}");
-        res.ok()
-    };
+    result.install_root = install_data
+        .install_root()
+        .clone()
+        .unwrap_or_else(|| local_install_dir().to_string_lossy().to_string());
 
-    if res.is_none() {
-        tracing::info!("Working offline.");
+    let loaded = state.source.load(force_work_offline).await?;
+    result.offline = loaded.offline;
+    result.manifest_source = state.source.describe();
+    result.required_installer = loaded.required_installer.map(|v| v.to_string());
+
+    let Some(body) = loaded.manifest else {
+        tracing::info!("No usable manifest, only showing installed products.");
         // Work offline
         // Load installed products
         for (prod_id, prod) in install_data.products() {
@@ -185,46 +210,101 @@ async fn load_manifest<R: Runtime>(
                 name: prod.name().clone(),
                 icon: prod.icon().clone(),
                 local_version: prod.version().clone(),
-                remote_version: "0.0.0".to_string(),
-                remote_version_prerelease: "0.0.0".to_string(),
+                remote_version: None,
+                latest_version: None,
+                update_available: false,
+                downgrade_needed: false,
+                recommended_version: None,
                 description: prod.description().clone(),
-                has_os_match_prerelease: prod.main_executable().is_some(),
-                has_os_match: prod.main_executable().is_some(),
                 can_start: prod.main_executable().is_some(),
-                allow_prerelease: *prod.use_prerelease(),
+                channel: prod.channel().clone(),
+                pin: prod.pin().as_ref().map(|p| p.to_string()),
+                held_version: None,
+                has_user_data: !prod.user_data().is_empty(),
+                installed_size: *prod.installed_size(),
+                local_yanked: false,
+                yank_reason: None,
+                required_installer: None,
+                install_directory: prod.install_directory().clone(),
+                existing: None,
             });
         }
 
         *state.install_data.lock().unwrap() = install_data;
         return Ok(result);
+    };
+    if result.offline {
+        tracing::info!("Working offline from the cached manifest.");
     }
-
-    let body: Manifest = res
-        .unwrap()
-        .json()
-        .await
-        .map_err(|_| "Failed to read manifest".to_string())?;
-
+    result.manifest_fetched_at = loaded.fetched_at;
+    result.channels = body.available_channels();
     *state.manifest.lock().unwrap() = Some(body.clone());
-    tracing::debug!("Fetched manifest.");
 
     // Detect products to present to frontend, current install status and upgrade possibility and notify frontend
     for prod in body.products() {
         let install_prod = install_data.products().get(prod.id());
+        let pin = install_prod.and_then(|p| p.pin().as_ref());
+        let channel = body.channel_for(
+            install_prod.and_then(|p| p.channel().as_deref()),
+            install_prod.is_some_and(|p| *p.use_prerelease()),
+        );
+        let local_version = install_prod
+            .and_then(|p| p.version().as_ref())
+            .and_then(|v| Version::parse(v).ok())
+            .and_then(|v| prod.versions().iter().find(|pv| *pv.version() == v));
+        let local_yanked = local_version.is_some_and(|v| *v.yanked());
+        if local_yanked {
+            tracing::warn!("The installed version of {} has been yanked", prod.id());
+        }
+        let installed = install_prod
+            .and_then(|p| p.version().as_ref())
+            .and_then(|v| Version::parse(v).ok());
+        let remote_version = prod
+            .best_version_for_this_system(&channel, pin)
+            .map(|(v, _)| v);
+        // Going back a version is only worth offering if something is wrong with the installed one
+        let (update_available, downgrade_needed) = match (&installed, &remote_version) {
+            (Some(installed), Some(remote)) => (
+                remote > installed,
+                remote < installed && (local_yanked || !manifest::is_within_pin(installed, pin)),
+            ),
+            _ => (false, false),
+        };
         result.products.push(ManifestLoadResultProduct {
             id: prod.id().clone(),
             name: prod.name().clone(),
             icon: prod.icon().clone(),
             local_version: install_prod.and_then(|p| p.version().clone()),
-            remote_version: prod.latest_version(false).to_string(),
-            remote_version_prerelease: prod.latest_version(true).to_string(),
+            remote_version: remote_version.map(|v| v.to_string()),
+            latest_version: prod
+                .latest_version_for_this_machine(&channel, pin)
+                .map(|v| v.version().to_string()),
+            update_available,
+            downgrade_needed,
+            recommended_version: installed
+                .as_ref()
+                .filter(|_| local_yanked)
+                .and_then(|v| prod.nearest_version_for_this_system(v, &channel, pin))
+                .map(|v| v.to_string()),
             description: prod.description().clone(),
-            has_os_match: prod.latest_version_data(false).is_some(),
-            has_os_match_prerelease: prod.latest_version_data(true).is_some(),
             can_start: install_prod
                 .map(|p| p.main_executable().is_some())
                 .unwrap_or(false),
-            allow_prerelease: install_prod.map(|p| *p.use_prerelease()).unwrap_or(false),
+            channel: Some(channel.id().clone()),
+            pin: pin.map(|p| p.to_string()),
+            held_version: prod.held_version(&channel, pin).map(|v| v.to_string()),
+            has_user_data: !prod.user_data().is_empty(),
+            installed_size: install_prod.and_then(|p| *p.installed_size()),
+            local_yanked,
+            yank_reason: local_version
+                .filter(|_| local_yanked)
+                .and_then(|v| v.yank_reason().clone()),
+            required_installer: prod
+                .required_installer(&channel, pin)
+                .map(|v| v.to_string()),
+            install_directory: installed_layout(&install_data, Some(prod), prod.id())
+                .map(|(dir, _)| dir.to_string_lossy().to_string()),
+            existing: adopt::find(&install_data, prod),
         });
     }
 
@@ -233,19 +313,60 @@ async fn load_manifest<R: Runtime>(
     Ok(result)
 }
 
+/// Choose the release channel a product follows.
 #[tauri::command]
-fn set_prerelease<R: Runtime>(
+fn set_channel<R: Runtime>(
     _app: tauri::AppHandle<R>,
     state: tauri::State<'_, AppData>,
     _window: tauri::Window<R>,
     id: String,
-    allow_prerelease: bool,
+    channel: String,
 ) -> Result<(), String> {
+    let use_prerelease = {
+        let mf_mutex = state.manifest.lock().unwrap();
+        let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
+        let channel = mf
+            .available_channels()
+            .into_iter()
+            .find(|c| *c.id() == channel)
+            .ok_or(format!("No channel with ID {channel}"))?;
+        channel.has_prereleases()
+    };
     let mut install_data = state.install_data.lock().unwrap();
-    tracing::debug!("Changing prerelease to {allow_prerelease} for app {id}.");
+    tracing::debug!("Changing channel to {channel} for app {id}.");
     let prod = install_data.get_mut_product_or_default(id);
-    prod.set_use_prerelease(allow_prerelease);
-    install_data.save().unwrap();
+    prod.set_channel(Some(channel));
+    // Kept in step for older installers reading the same install data
+    prod.set_use_prerelease(use_prerelease);
+    install_data
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    Ok(())
+}
+
+/// Hold a product within a range of versions, such as `2.x`, or release it
+/// if `pin` is empty.
+#[tauri::command]
+fn set_pin<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+    pin: Option<String>,
+) -> Result<(), String> {
+    let pin = pin
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| {
+            VersionReq::parse(p.trim()).map_err(|e| format!("Invalid version range {p:?}: {e}"))
+        })
+        .transpose()?;
+    let mut install_data = state.install_data.lock().unwrap();
+    tracing::debug!("Changing pin to {pin:?} for app {id}.");
+    let prod = install_data.get_mut_product_or_default(id);
+    prod.set_pin(pin);
+    install_data
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
     Ok(())
 }
 
@@ -261,33 +382,162 @@ fn expand_env_vars(input: &str) -> String {
     .to_string()
 }
 
+/// What changed in a version of a product
+#[derive(Serialize)]
+struct ReleaseNotes {
+    /// The version these notes are for
+    version: String,
+    /// When the version was released, as an ISO 8601 date
+    released_at: Option<String>,
+    /// The notes as Markdown, if they are in the manifest
+    notes: Option<String>,
+    /// A link to the notes, if they are published elsewhere
+    url: Option<String>,
+}
+
+/// Get the release notes for every version an update to a product would
+/// bring in, newest first.
+#[tauri::command]
+async fn release_notes<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+) -> Result<Vec<ReleaseNotes>, String> {
+    let (current_version, channel, use_prerelease, pin) = {
+        let install = state.install_data.lock().unwrap();
+        let prod_install = install.products().get(&id);
+        (
+            prod_install
+                .and_then(|p| p.version().as_ref())
+                .and_then(|v| Version::parse(v).ok())
+                .unwrap_or(Version::new(0, 0, 0)),
+            prod_install.and_then(|p| p.channel().clone()),
+            prod_install.is_some_and(|p| *p.use_prerelease()),
+            prod_install.and_then(|p| p.pin().clone()),
+        )
+    };
+    let mf_mutex = state.manifest.lock().unwrap();
+    let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
+    let prod = mf
+        .products()
+        .iter()
+        .find(|p| *p.id() == id)
+        .ok_or("Product not found!".to_string())?;
+    let channel = mf.channel_for(channel.as_deref(), use_prerelease);
+    let Some((target, _)) = prod.best_version_for_this_system(&channel, pin.as_ref()) else {
+        return Ok(vec![]);
+    };
+
+    Ok(prod
+        .versions_between(&current_version, &target, &channel)
+        .into_iter()
+        .map(|v| {
+            let url = v.release_notes_url().map(|u| u.to_string());
+            ReleaseNotes {
+                version: v.version().to_string(),
+                released_at: v.released_at().clone(),
+                notes: v.release_notes().clone().filter(|_| url.is_none()),
+                url,
+            }
+        })
+        .collect())
+}
+
 #[tauri::command]
 async fn install_app<R: Runtime>(
     _app: tauri::AppHandle<R>,
     state: tauri::State<'_, AppData>,
     _window: tauri::Window<R>,
     id: String,
-) -> Result<(), String> {
-    tracing::info!("Installing app {id}.");
+    version: Option<String>,
+) -> Result<OperationSummary, String> {
+    let version = version
+        .map(|v| Version::parse(&v).map_err(|e| format!("Invalid version {v:?}: {e}")))
+        .transpose()?;
     let mut install = state.install_data.lock().unwrap().clone();
     let mf = {
         let mf_mutex = state.manifest.lock().unwrap();
-        mf_mutex.clone().unwrap()
+        mf_mutex.clone().ok_or(NO_MANIFEST_ERROR.to_string())?
     };
+    let summary = install_product(&mut install, &mf, &id, version.as_ref()).await?;
+    *state.install_data.lock().unwrap() = install;
+    Ok(summary)
+}
+
+/// Install a product, or update it, to `version` if given or otherwise the
+/// best version for this system in its channel and pin, saving the install
+/// data once done.
+async fn install_product(
+    install: &mut Install,
+    mf: &Manifest,
+    id: &str,
+    version: Option<&Version>,
+) -> Result<OperationSummary, String> {
+    tracing::info!("Installing app {id}.");
     for prod in mf.products() {
-        if *prod.id() == id {
-            let mut install_directory = local_install_dir();
-            install_directory.push(prod.install_directory());
-            let install_directory = install_directory;
+        if prod.id() == id {
+            if !prod.is_supported() {
+                return Err(format!(
+                    "{} needs a newer installer. Update the installer and try again.",
+                    prod.name()
+                ));
+            }
+            let (install_directory, _) = installed_layout(install, Some(prod), id)
+                .ok_or("Product not found!".to_string())?;
             tracing::info!("Installing to {install_directory:?}");
 
-            let prod_install = install.get_mut_product_or_default(id);
+            let prod_install = install.get_mut_product_or_default(id.to_string());
             let current_version = prod_install
                 .version()
                 .clone()
                 .map(|v| Version::parse(&v).unwrap());
-            let version = prod.latest_version(*prod_install.use_prerelease());
+            let pin = prod_install.pin().clone();
+            let channel = mf.channel_for(
+                prod_install.channel().as_deref(),
+                *prod_install.use_prerelease(),
+            );
+            let (version, download) = match version {
+                Some(version) => {
+                    let chosen = prod
+                        .versions()
+                        .iter()
+                        .find(|v| v.version() == version && !v.yanked() && v.is_supported())
+                        .ok_or(format!("v{version} can't be installed"))?;
+                    let download = chosen
+                        .downloads()
+                        .for_this_system()
+                        .ok_or("Download not available for this operating system".to_string())?;
+                    (version.clone(), download)
+                }
+                None => prod
+                    .best_version_for_this_system(&channel, pin.as_ref())
+                    .ok_or("Download not available for this operating system".to_string())?,
+            };
             tracing::debug!("Local version {current_version:?}, remote version: {version}");
+            tracing::debug!("Download spec: {download:?}");
+
+            // Make sure there is room for the product before touching anything
+            if let Some(size) = download.installed_size().or(*download.size()) {
+                disk::ensure_free_space(&install_directory, size)?;
+            }
+
+            // Download to temporary file (via chunks)
+            let tempdir = tempfile::tempdir().unwrap();
+            let downloaded = artifact::download(&download, tempdir.path()).await?;
+            let tempfile = downloaded.path;
+
+            // Everything except MSIs is staged first so we know exactly which
+            // files make up this version before touching the installed ones.
+            let staging = tempdir.path().join("staging");
+            let files = if matches!(download.strategy(), DownloadStrategy::Msi { .. }) {
+                None
+            } else {
+                artifact::stage(download.strategy(), &tempfile, &staging)?;
+                let files = inventory::build(&staging, prod.user_data())
+                    .map_err(|e| format!("Failed to read installed files: {e}"))?;
+                Some(files)
+            };
 
             // Determine any removals
             if let Some(v) = current_version {
@@ -336,105 +586,59 @@ async fn install_app<R: Runtime>(
                 }
             }
 
-            // Install
-            fs::create_dir_all(&install_directory).unwrap();
+            // Keep user data out of the way of the new files, and put it back
+            // even if they can't be installed.
+            let user_data_backup = local_user_data_backup_dir(prod.id());
+            let installed =
+                userdata::backup(&install_directory, &user_data_backup, prod.user_data())
+                    .map_err(|e| format!("Failed to back up user data: {e}"))
+                    .and_then(|()| {
+                        fs::create_dir_all(&install_directory)
+                            .map_err(|e| format!("Failed to create install directory: {e}"))?;
+                        if let DownloadStrategy::Msi { product_code } = download.strategy() {
+                            // First, uninstall any current version
+                            std::process::Command::new("msiexec.exe")
+                                .arg("/x")
+                                .arg(product_code)
+                                .arg("/q")
+                                .output()
+                                .map_err(|e| format!("Failed to uninstall old versions: {e}"))?;
 
-            let download = prod.latest_version_data(*prod_install.use_prerelease());
-            if download.is_none() {
-                return Err("Download not available for this operating system".to_string());
-            }
-            let download = download.unwrap();
-            tracing::debug!("Download spec: {download:?}");
-
-            // Download to temporary file (via chunks)
-            let tempdir = tempfile::tempdir().unwrap();
-            let mut tempfile = tempdir.path().to_path_buf();
-            tempfile.push("data");
-            let mut req = reqwest::get(download.url())
-                .await
-                .map_err(|e| format!("Failed to get data: {e}"))?;
-            tracing::debug!("download response: {req:?}");
-
-            {
-                let mut writer = BufWriter::new(
-                    fs::File::create(&tempfile)
-                        .map_err(|e| format!("Failed to create temporary file: {e}"))?,
-                );
-                while let Some(data) = req
-                    .chunk()
-                    .await
-                    .map_err(|e| format!("Failed to get data: {e}"))?
-                {
-                    writer
-                        .write_all(&data)
-                        .map_err(|e| format!("Failed to write data: {e}"))?;
-                }
-                tracing::debug!("File downloaded");
-            }
-
-            // Evaluate strategy
-            match download.strategy() {
-                DownloadStrategy::File { name, chmod } => {
-                    let mut path = install_directory.clone();
-                    path.push(name);
-
-                    fs::copy(tempfile, &path)
-                        .map_err(|e| format!("Failed to create target file: {e}"))?;
-
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-
-                        if *chmod {
-                            tracing::debug!("chmod'ing file");
-                            let mut perms = fs::metadata(&path)
-                                .map_err(|e| format!("Failed to set permissions: {e}"))?
-                                .permissions();
-                            perms.set_mode(perms.mode() | 0o100);
-                            fs::set_permissions(path, perms)
-                                .map_err(|e| format!("Failed to set permissions: {e}"))?;
+                            // Next, install new version
+                            std::process::Command::new("msiexec.exe")
+                                .arg("/i")
+                                .arg(&tempfile)
+                                .arg("/qr")
+                                .arg("ALLUSERS=2")
+                                .arg("MSIINSTALLPERUSER=1")
+                                .output()
+                                .map_err(|e| format!("Failed to install new version: {e}"))?;
+                        } else if let Some(files) = &files {
+                            tracing::debug!("Installing {} files", files.len());
+                            inventory::apply(
+                                &staging,
+                                &install_directory,
+                                prod_install.files(),
+                                files,
+                            )
+                            .map_err(|e| format!("Failed to install files: {e}"))?;
+                            // User data is only shipped as defaults, so it always comes
+                            // across even though it isn't part of the inventory.
+                            userdata::transfer(&staging, &install_directory, prod.user_data())
+                                .map_err(|e| format!("Failed to install default user data: {e}"))?;
                         }
-                    }
-                    #[cfg(not(unix))]
-                    {
-                        if *chmod {
-                            // Do nothing, just to shut up compiler warnings…
-                        }
-                    }
-                }
-                DownloadStrategy::Msi { product_code } => {
-                    // First, uninstall any current version
-                    std::process::Command::new("msiexec.exe")
-                        .arg("/x")
-                        .arg(product_code)
-                        .arg("/q")
-                        .output()
-                        .map_err(|e| format!("Failed to uninstall old versions: {e}"))?;
-
-                    // Next, install new version
-                    std::process::Command::new("msiexec.exe")
-                        .arg("/i")
-                        .arg(tempfile)
-                        .arg("/qr")
-                        .arg("ALLUSERS=2")
-                        .arg("MSIINSTALLPERUSER=1")
-                        .output()
-                        .map_err(|e| format!("Failed to install new version: {e}"))?;
-
-                    prod_install.set_msi_product_code(Some(product_code.clone()));
-                }
-                DownloadStrategy::ZipFile => {
-                    let reader = BufReader::new(
-                        fs::File::open(&tempfile)
-                            .map_err(|e| format!("Failed to open temporary file: {e}"))?,
-                    );
-                    zip_extract::extract(reader, &install_directory, true)
-                        .map_err(|e| format!("Failed to extract data: {e}"))?;
-                }
-                DownloadStrategy::GzippedTarball => {
-                    gzip::extract_tar_gz(tempfile, &install_directory)
-                        .map_err(|e| format!("Failed to extract data: {e}"))?;
-                }
+                        Ok(())
+                    });
+            let restored =
+                userdata::restore(&user_data_backup, &install_directory, prod.user_data())
+                    .map_err(|e| format!("Failed to restore user data: {e}"));
+            installed?;
+            restored?;
+            if let DownloadStrategy::Msi { product_code } = download.strategy() {
+                prod_install.set_msi_product_code(Some(product_code.clone()));
+            }
+            if let Some(files) = files {
+                prod_install.set_files(files);
             }
 
             tracing::info!("Install complete, saving data");
@@ -442,6 +646,7 @@ async fn install_app<R: Runtime>(
             prod_install.set_description(prod.description().clone());
             prod_install.set_icon(prod.icon().clone());
             prod_install.set_version(Some(version.to_string()));
+            prod_install.set_installed_size(disk::directory_size(&install_directory).ok());
             if let Some(exec) = download.executable() {
                 let mut main_exec_path = install_directory.clone();
                 main_exec_path.push(exec);
@@ -457,12 +662,19 @@ async fn install_app<R: Runtime>(
                     install_directory.to_string_lossy().to_string(),
                 ));
             }
+            prod_install.set_installed_from(Some(downloaded.source.clone()));
+            prod_install
+                .set_install_directory(Some(install_directory.to_string_lossy().to_string()));
+            prod_install.set_removed_from(None);
+            prod_install.set_user_data(prod.user_data().clone());
             install
                 .save()
-                .expect("failed to update installer.json after uninstalling");
-            *state.install_data.lock().unwrap() = install;
-            tracing::info!("Done");
-            return Ok(());
+                .map_err(|e| format!("Failed to save install data: {e}"))?;
+            tracing::info!("Installed {id} v{version} from {}", downloaded.source);
+            return Ok(OperationSummary {
+                version: version.to_string(),
+                source: Some(downloaded.source),
+            });
         }
     }
     Err("No matching product found".to_string())
@@ -474,49 +686,411 @@ async fn remove_app<R: Runtime>(
     state: tauri::State<'_, AppData>,
     _window: tauri::Window<R>,
     id: String,
+    remove_user_data: bool,
+) -> Result<(), String> {
+    let mf = state.manifest.lock().unwrap().clone();
+    let mut install = state.install_data.lock().unwrap();
+    let prod = mf
+        .as_ref()
+        .and_then(|mf| mf.products().iter().find(|p| *p.id() == id));
+    remove_product(&mut install, prod, &id, remove_user_data)
+}
+
+/// Remove a product, keeping its user data for a later install unless
+/// `remove_user_data`, and save the install data once done.
+fn remove_product(
+    install: &mut Install,
+    prod: Option<&Product>,
+    id: &str,
+    remove_user_data: bool,
 ) -> Result<(), String> {
     // Find install directory for app ID, then delete.
+    let (install_directory, user_data) =
+        installed_layout(install, prod, id).ok_or("Product not found!".to_string())?;
+
+    let user_data_backup = local_user_data_backup_dir(id);
+    if remove_user_data {
+        tracing::info!("Removing user data backup {user_data_backup:?}");
+        userdata::remove_path(&user_data_backup)
+            .map_err(|e| format!("Failed to remove user data: {e}"))?;
+    } else {
+        userdata::backup(&install_directory, &user_data_backup, &user_data)
+            .map_err(|e| format!("Failed to back up user data: {e}"))?;
+    }
+
+    tracing::info!("Removing from local manifest");
+    let prod_install = install.get_mut_product_or_default(id.to_string());
+
+    if remove_user_data || prod_install.files().is_empty() {
+        tracing::info!("Removing {install_directory:?}");
+        if let Err(e) = fs::remove_dir_all(&install_directory) {
+            // We can ignore this as it may just not exist.
+            tracing::warn!("Failed to delete directory: {e}");
+        }
+    } else {
+        tracing::info!("Removing installed files from {install_directory:?}");
+        inventory::remove(&install_directory, prod_install.files().keys())
+            .map_err(|e| format!("Failed to remove files: {e}"))?;
+    }
+
+    if let Some(product_code) = prod_install.msi_product_code() {
+        tracing::info!("Removing MSI");
+        std::process::Command::new("msiexec.exe")
+            .arg("/x")
+            .arg(product_code)
+            .arg("/q")
+            .output()
+            .map_err(|e| format!("Failed to uninstall old MSI: {e}"))?;
+    }
+
+    prod_install.set_msi_product_code(None);
+    prod_install.set_files(Default::default());
+    prod_install.set_version(None);
+    prod_install.set_installed_from(None);
+    prod_install.set_installed_size(None);
+    prod_install.set_install_directory(None);
+    prod_install.set_removed_from(Some(install_directory.to_string_lossy().to_string()));
+    prod_install.set_user_data(vec![]);
+    prod_install.set_main_executable(None);
+    prod_install.set_execute_working_directory(None);
+    install
+        .save()
+        .expect("failed to update installer.json after uninstalling");
+
+    tracing::info!("Done");
+    Ok(())
+}
+
+/// Find where a product is installed and which of its paths hold user data,
+/// preferring what was recorded at install time so this works without the
+/// manifest. Products installed before that was recorded are found in their
+/// own directory under the default install directory.
+fn installed_layout(
+    install: &Install,
+    prod: Option<&Product>,
+    id: &str,
+) -> Option<(PathBuf, Vec<String>)> {
+    if let Some(prod_install) = install.products().get(id) {
+        if let Some(dir) = prod_install.install_directory() {
+            let user_data = prod
+                .map(|p| p.user_data().clone())
+                .unwrap_or_else(|| prod_install.user_data().clone());
+            return Some((PathBuf::from(dir), user_data));
+        }
+    }
+    let Some(prod) = prod else {
+        let prod_install = install.products().get(id)?;
+        prod_install.version().as_ref()?;
+        return Some((
+            local_install_dir().join(id),
+            prod_install.user_data().clone(),
+        ));
+    };
+    Some((
+        install.install_root_for(id).join(prod.install_directory()),
+        prod.user_data().clone(),
+    ))
+}
+
+/// Get new versions before their rollout reaches this machine, or wait for it.
+#[tauri::command]
+fn set_early_access<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut install_data = state.install_data.lock().unwrap();
+    tracing::debug!("Changing early access to {enabled}.");
+    install_data.set_early_access(enabled);
+    rollout::follow_setting(&install_data);
+    install_data
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    Ok(())
+}
+
+/// Choose the directory every product not yet installed is installed under,
+/// or go back to the usual one if `root` is empty.
+#[tauri::command]
+fn set_install_root<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    root: Option<String>,
+) -> Result<(), String> {
+    let root = root.filter(|r| !r.trim().is_empty());
+    let mut install_data = state.install_data.lock().unwrap();
+    tracing::debug!("Changing install root to {root:?}.");
+    install_data.set_install_root(root);
+    install_data
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    Ok(())
+}
+
+/// Choose the directory a product is installed under, or go back to the one
+/// for every product if `root` is empty. An installed product is moved there,
+/// keeping the directory the manifest gives it.
+#[tauri::command]
+fn move_app<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+    root: Option<String>,
+) -> Result<(), String> {
+    let root = root.filter(|r| !r.trim().is_empty());
+    let mf = state.manifest.lock().unwrap().clone();
+    let mut install = state.install_data.lock().unwrap();
+    let prod = mf
+        .as_ref()
+        .and_then(|mf| mf.products().iter().find(|p| *p.id() == id));
+
+    let installed = install.products().get(&id).and_then(|p| {
+        p.install_directory()
+            .as_ref()
+            .map(|d| (PathBuf::from(d), p))
+    });
+    let old_directory = match installed {
+        Some((_, prod_install)) if prod_install.msi_product_code().is_some() => {
+            return Err("Products installed by an MSI can't be moved".to_string());
+        }
+        Some((old_directory, _)) => Some(old_directory),
+        None => None,
+    };
+
+    // The new root is only recorded once the files have moved, so a failed move changes nothing
+    if let Some(old_directory) = old_directory {
+        let relative = prod
+            .map(|p| PathBuf::from(p.install_directory()))
+            .or(old_directory.file_name().map(PathBuf::from))
+            .ok_or("Product not found!".to_string())?;
+        let new_root = root
+            .clone()
+            .or_else(|| install.install_root().clone())
+            .map(PathBuf::from)
+            .unwrap_or_else(local_install_dir);
+        let new_directory = new_root.join(relative);
+        if new_directory != old_directory {
+            move_installation(&mut install, &id, &old_directory, &new_directory)?;
+        }
+    }
+    install
+        .get_mut_product_or_default(id)
+        .set_install_root(root);
+    install
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    Ok(())
+}
+
+/// Move an installed product's files from `from` to `to`, and update where it
+/// is started from.
+fn move_installation(
+    install: &mut Install,
+    id: &str,
+    from: &Path,
+    to: &Path,
+) -> Result<(), String> {
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    let prod_install = install.get_mut_product_or_default(id.to_string());
+    if let Some(size) = *prod_install.installed_size() {
+        disk::ensure_free_space(to, size)?;
+    }
+    tracing::info!("Moving {from:?} to {to:?}");
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {parent:?}: {e}"))?;
+    }
+    userdata::move_path(from, to).map_err(|e| format!("Failed to move installation: {e}"))?;
+    prod_install.rebase(from, to);
+    Ok(())
+}
+
+/// Write the installed products, their channels and pins, and the environment
+/// variables that don't look secret to a profile others can import.
+#[tauri::command]
+fn export_profile<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    path: PathBuf,
+    exact: bool,
+) -> Result<(), String> {
+    let mf_mutex = state.manifest.lock().unwrap();
+    let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
+    let install = state.install_data.lock().unwrap();
+    let environment = profile::read_environment(&local_environment_file());
+    profile::Profile::from_install(&install, mf, environment, exact).save(&path)?;
+    tracing::info!("Exported profile to {path:?}");
+    Ok(())
+}
+
+/// Work out what importing a profile would install, update and remove.
+#[tauri::command]
+fn preview_profile<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    path: PathBuf,
+) -> Result<profile::Plan, String> {
+    let profile = profile::Profile::load(&path)?;
     let mf_mutex = state.manifest.lock().unwrap();
+    let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
+    Ok(profile.plan(mf, &state.install_data.lock().unwrap()))
+}
+
+/// Take the channels, pins and environment variables from a profile. The
+/// returned plan is then carried out one product at a time.
+#[tauri::command]
+fn import_profile<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    path: PathBuf,
+) -> Result<profile::Plan, String> {
+    let profile = profile::Profile::load(&path)?;
+    let mf_mutex = state.manifest.lock().unwrap();
+    let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
     let mut install = state.install_data.lock().unwrap();
-    let mf = mf_mutex.as_ref().unwrap();
-    for prod in mf.products() {
-        if *prod.id() == id {
-            let mut install_directory = local_install_dir();
-            install_directory.push(prod.install_directory());
-            let install_directory = install_directory;
-
-            tracing::info!("Removing {install_directory:?}");
-            if let Err(e) = fs::remove_dir_all(install_directory) {
-                // We can ignore this as it may just not exist.
-                tracing::warn!("Failed to delete directory: {e}");
-            }
+    let plan = profile.plan(mf, &install);
+    profile.apply_settings(mf, &mut install);
+    install
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    profile::write_environment(&local_environment_file(), &profile.environment)
+        .map_err(|e| format!("Failed to save environment variables: {e}"))?;
+    tracing::info!("Imported profile from {path:?}");
+    Ok(plan)
+}
 
-            tracing::info!("Removing from local manifest");
-            let prod_install = install.get_mut_product_or_default(id);
-
-            if let Some(product_code) = prod_install.msi_product_code() {
-                tracing::info!("Removing MSI");
-                std::process::Command::new("msiexec.exe")
-                    .arg("/x")
-                    .arg(product_code)
-                    .arg("/q")
-                    .output()
-                    .map_err(|e| format!("Failed to uninstall old MSI: {e}"))?;
-            }
+/// Record a copy of a product found where it would be installed as installed.
+#[tauri::command]
+fn adopt_app<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+) -> Result<OperationSummary, String> {
+    let mf_mutex = state.manifest.lock().unwrap();
+    let mf = mf_mutex.as_ref().ok_or(NO_MANIFEST_ERROR.to_string())?;
+    let prod = mf
+        .products()
+        .iter()
+        .find(|p| *p.id() == id)
+        .ok_or("Product not found!".to_string())?;
+    let mut install = state.install_data.lock().unwrap();
+    let version = adopt::adopt(&mut install, prod)?;
+    install
+        .save()
+        .map_err(|e| format!("Failed to save install data: {e}"))?;
+    tracing::info!("Adopted {id} v{version}");
+    Ok(OperationSummary {
+        version: version.to_string(),
+        source: None,
+    })
+}
 
-            prod_install.set_msi_product_code(None);
-            prod_install.set_version(None);
-            prod_install.set_main_executable(None);
-            prod_install.set_execute_working_directory(None);
-            install
-                .save()
-                .expect("failed to update installer.json after uninstalling");
+#[tauri::command]
+fn verify_app<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+) -> Result<Option<inventory::Verification>, String> {
+    let mf = state.manifest.lock().unwrap().clone();
+    let install = state.install_data.lock().unwrap();
+    let prod = mf
+        .as_ref()
+        .and_then(|mf| mf.products().iter().find(|p| *p.id() == id));
+    let prod_install = install
+        .products()
+        .get(&id)
+        .ok_or("Product not installed!".to_string())?;
+    if prod_install.files().is_empty() {
+        // Installed before we kept track of files, or by an MSI
+        return Ok(None);
+    }
+    let (install_directory, user_data) =
+        installed_layout(&install, prod, &id).ok_or("Product not found!".to_string())?;
 
-            tracing::info!("Done");
-            return Ok(());
-        }
+    tracing::info!("Verifying {install_directory:?}");
+    let verification = inventory::verify(&install_directory, prod_install.files(), &user_data)
+        .map_err(|e| format!("Failed to verify installation: {e}"))?;
+    tracing::info!(
+        "{} missing, {} modified, {} extra files",
+        verification.missing.len(),
+        verification.modified.len(),
+        verification.extra.len()
+    );
+    Ok(Some(verification))
+}
+
+#[tauri::command]
+async fn repair_app<R: Runtime>(
+    _app: tauri::AppHandle<R>,
+    state: tauri::State<'_, AppData>,
+    _window: tauri::Window<R>,
+    id: String,
+) -> Result<OperationSummary, String> {
+    tracing::info!("Repairing app {id}.");
+    let mut install = state.install_data.lock().unwrap().clone();
+    let mf = {
+        let mf_mutex = state.manifest.lock().unwrap();
+        mf_mutex.clone().ok_or(NO_MANIFEST_ERROR.to_string())?
+    };
+    let prod = mf
+        .products()
+        .iter()
+        .find(|p| *p.id() == id)
+        .ok_or("Product not found!".to_string())?;
+    let (install_directory, user_data) =
+        installed_layout(&install, Some(prod), &id).ok_or("Product not found!".to_string())?;
+    let prod_install = install.get_mut_product_or_default(id.clone());
+    let version = prod_install
+        .version()
+        .clone()
+        .map(|v| Version::parse(&v).unwrap())
+        .ok_or("Product not installed!".to_string())?;
+
+    if let Some(product_code) = prod_install.msi_product_code() {
+        tracing::info!("Repairing MSI");
+        std::process::Command::new("msiexec.exe")
+            .arg("/fa")
+            .arg(product_code)
+            .arg("/qr")
+            .output()
+            .map_err(|e| format!("Failed to repair MSI: {e}"))?;
+        return Ok(OperationSummary {
+            version: version.to_string(),
+            source: None,
+        });
     }
-    Err("Product not found!".to_string())
+
+    let download = prod.version_data(&version).ok_or(format!(
+        "Version {version} is no longer available to repair from"
+    ))?;
+
+    let tempdir = tempfile::tempdir().unwrap();
+    let downloaded = artifact::download(&download, tempdir.path()).await?;
+    let staging = tempdir.path().join("staging");
+    artifact::stage(download.strategy(), &downloaded.path, &staging)?;
+    let files = inventory::repair(&staging, &install_directory, &user_data)
+        .map_err(|e| format!("Failed to repair files: {e}"))?;
+
+    prod_install.set_files(files);
+    prod_install.set_installed_size(disk::directory_size(&install_directory).ok());
+    install
+        .save()
+        .expect("failed to update installer.json after repairing");
+    *state.install_data.lock().unwrap() = install;
+    tracing::info!("Repaired {id} v{version} from {}", downloaded.source);
+    Ok(OperationSummary {
+        version: version.to_string(),
+        source: Some(downloaded.source),
+    })
 }
 
 #[tauri::command]
@@ -603,6 +1177,7 @@ fn build_updater<R: Runtime>(
     }
 
     app.updater_builder()
+        .configure_client(http::configure)
         .endpoints(endpoints)
         .and_then(|b| b.build())
 }
@@ -628,28 +1203,146 @@ fn can_auto_update() -> bool {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+    if env::args_os().len() > 1 {
+        cli::attach_console();
+    }
+    let cli = cli::Cli::parse();
+    let source = cli.source().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    if let Some(command) = cli.command {
+        std::process::exit(cli::execute(command, &source));
+    }
+
+    let mut builder = tauri::Builder::default();
+    // Installers with their own root are separate environments, so can run side by side
+    if paths::root_override().is_none() {
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
             let _ = app
                 .get_webview_window("main")
                 .expect("main window must be present")
                 .set_focus();
-        }))
+        }));
+    }
+    builder
         .plugin(tauri_plugin_dialog::init())
-        .setup(|app| {
-            app.manage(AppData::default());
+        .setup(move |app| {
+            let handle = app.handle().clone();
+            let revision = AtomicU32::new(0);
+            let watcher = source
+                .watch(move || {
+                    let revision = revision.fetch_add(1, Ordering::Relaxed) + 1;
+                    if let Err(e) = handle.emit("manifest-changed", revision) {
+                        tracing::warn!("Failed to notify UI of manifest change: {e}");
+                    }
+                })
+                .unwrap_or_else(|e| {
+                    tracing::warn!("{e}");
+                    None
+                });
+            app.manage(AppData {
+                source,
+                _watcher: watcher,
+                ..Default::default()
+            });
             Ok(())
         })
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             load_manifest,
-            set_prerelease,
+            set_channel,
+            set_install_root,
+            set_early_access,
+            move_app,
+            adopt_app,
+            export_profile,
+            preview_profile,
+            import_profile,
+            set_pin,
+            release_notes,
             install_app,
             remove_app,
             start_app,
+            verify_app,
+            repair_app,
             update_installer,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn failed_installs_leave_user_data_in_place() {
+        let root = tempfile::tempdir().unwrap();
+        let install_directory = root.path().join("p");
+        fs::create_dir_all(install_directory.join("config/nested")).unwrap();
+        fs::write(install_directory.join("config/nested/settings.txt"), "mine").unwrap();
+        let missing = Url::from_file_path(root.path().join("missing.zip")).unwrap();
+        let download = json!({ "url": missing.as_str(), "strategy": "ZipFile" });
+        let manifest = json!({
+            "products": [{
+                "id": "p",
+                "name": "P",
+                "description": "",
+                "install_directory": "p",
+                "removals": [],
+                "user_data": ["config"],
+                "versions": [{
+                    "version": "2.0.0",
+                    "downloads": {
+                        "windows": download,
+                        "mac": download,
+                        "mac-intel": download,
+                        "linux": download,
+                    },
+                }],
+            }],
+        });
+        let manifest = Manifest::parse(manifest.to_string().as_bytes()).unwrap();
+        let mut install: Install = serde_json::from_value(json!({
+            "products": {
+                "p": {
+                    "version": "1.0.0",
+                    "install_directory": install_directory,
+                    "user_data": ["config"],
+                    "use_prerelease": false,
+                },
+            },
+        }))
+        .unwrap();
+
+        let result =
+            tauri::async_runtime::block_on(install_product(&mut install, &manifest, "p", None));
+
+        assert!(result.is_err());
+        assert_eq!(
+            fs::read_to_string(install_directory.join("config/nested/settings.txt")).unwrap(),
+            "mine"
+        );
+    }
+
+    #[test]
+    fn old_installs_are_found_without_the_manifest() {
+        let install: Install = serde_json::from_value(json!({
+            "products": {
+                "old": { "version": "1.0.0", "use_prerelease": false },
+                "removed": { "use_prerelease": false },
+            },
+        }))
+        .unwrap();
+
+        let (dir, user_data) = installed_layout(&install, None, "old").unwrap();
+        assert_eq!(dir, local_install_dir().join("old"));
+        assert!(user_data.is_empty());
+        assert!(installed_layout(&install, None, "removed").is_none());
+        assert!(installed_layout(&install, None, "unknown").is_none());
+    }
+}
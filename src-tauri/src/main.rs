@@ -3,39 +3,8 @@
 
 use tracing_subscriber_multi::*;
 
-use std::path::PathBuf;
 use std::{env, sync::Mutex};
 
-#[cfg(target_os = "windows")]
-pub fn local_log_dir() -> PathBuf {
-    let exe_dir = std::env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-        .unwrap_or(PathBuf::from("."));
-
-    let test_file = exe_dir.join(".testio");
-    let writeable = std::fs::write(&test_file, []).is_ok();
-    let _ = std::fs::remove_file(&test_file);
-    tracing::debug!("Base logging path is writable: {writeable}");
-    if writeable {
-        exe_dir
-    } else {
-        // default to an appdata folder, probably installed as admin
-        let mut base = dirs::data_local_dir().unwrap();
-        base.push("AngelSuite");
-        std::fs::create_dir_all(&base).unwrap();
-        base
-    }
-}
-
-#[cfg(any(target_os = "macos", target_os = "linux"))]
-pub fn local_log_dir() -> PathBuf {
-    let mut base = dirs::data_local_dir().unwrap();
-    base.push("angelsuite");
-    std::fs::create_dir_all(&base).unwrap();
-    base
-}
-
 fn main() {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(
@@ -49,7 +18,7 @@ fn main() {
         .with_writer(Mutex::new(DualWriter::new(
             std::io::stderr(),
             AnsiStripper::new(RotatingFile::new(
-                local_log_dir().join("angelsuite-installer.log"),
+                angelsuite_installer_lib::local_log_dir().join("angelsuite-installer.log"),
                 AppendCount::new(3),
                 ContentLimit::Lines(1000),
                 Compression::OnRotate(0),
@@ -1,18 +1,148 @@
-use getset::Getters;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use getset::{Getters, Setters};
+use reqwest::Url;
+use schemars::JsonSchema;
 use semver::{Version, VersionReq};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::rollout;
+
+/// Get the version of this installer, which manifests can require a minimum of.
+pub fn installer_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("package version must be valid semver")
+}
+
+/// Does this installer meet a minimum installer version, if there is one?
+fn is_supported(required: Option<&Version>) -> bool {
+    required.is_none_or(|required| installer_version() >= *required)
+}
+
+/// Why a manifest couldn't be used
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The manifest needs at least this version of the installer
+    InstallerTooOld(Version),
+    /// The manifest couldn't be read
+    Invalid(String),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::InstallerTooOld(required) => write!(
+                f,
+                "The product list needs installer v{required} or newer, but this is v{}",
+                installer_version()
+            ),
+            ManifestError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Get the JSON Schema for the manifest format, derived from the types below.
+pub fn schema() -> schemars::Schema {
+    schemars::schema_for!(Manifest)
+}
 
 /// The remote manifest object
-#[derive(Clone, Debug, Deserialize, Getters)]
-#[getset(get = "pub")]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
 pub struct Manifest {
+    /// The oldest installer that can understand this manifest
+    min_installer_version: Option<Version>,
+    /// The release channels users can choose between, most stable first. Without any, there are `stable` and `prerelease` channels
+    #[serde(default)]
+    channels: Vec<Channel>,
     /// Available products
     products: Vec<Product>,
 }
 
+impl Manifest {
+    /// Read a manifest, checking it can be used by this installer first so a
+    /// manifest in a newer format gives a useful error.
+    pub fn parse(bytes: &[u8]) -> Result<Self, ManifestError> {
+        let value: serde_json::Value = serde_json::from_slice(bytes)
+            .map_err(|e| ManifestError::Invalid(format!("Failed to read manifest: {e}")))?;
+        let required = value
+            .get("min_installer_version")
+            .and_then(|v| v.as_str())
+            .and_then(|v| Version::parse(v).ok());
+        if !is_supported(required.as_ref()) {
+            return Err(ManifestError::InstallerTooOld(required.unwrap()));
+        }
+        serde_json::from_value(value)
+            .map_err(|e| ManifestError::Invalid(format!("Failed to read manifest: {e}")))
+    }
+
+    /// Get the channels users can choose between, most stable first.
+    pub fn available_channels(&self) -> Vec<Channel> {
+        if self.channels.is_empty() {
+            Channel::defaults()
+        } else {
+            self.channels.clone()
+        }
+    }
+
+    /// Find the channel a product should follow. Products without a channel
+    /// chosen follow the first channel, or if they were set to use prereleases
+    /// before there were channels, the first channel that has them.
+    pub fn channel_for(&self, chosen: Option<&str>, use_prerelease: bool) -> Channel {
+        let channels = self.available_channels();
+        if let Some(channel) = chosen.and_then(|id| channels.iter().find(|c| c.id == id)) {
+            return channel.clone();
+        }
+        let fallback = if use_prerelease {
+            channels
+                .iter()
+                .find(|c| c.prerelease.iter().any(|p| p == "*"))
+                .or(channels.last())
+        } else {
+            channels.first()
+        };
+        fallback.cloned().unwrap_or_else(Channel::stable)
+    }
+
+    /// Can this installer use this manifest?
+    pub fn is_supported(&self) -> bool {
+        is_supported(self.min_installer_version.as_ref())
+    }
+
+    /// Iterate over every download in the manifest
+    pub fn downloads_mut(&mut self) -> impl Iterator<Item = &mut DownloadSpec> {
+        self.products
+            .iter_mut()
+            .flat_map(|p| p.versions.iter_mut())
+            .flat_map(|v| {
+                [
+                    v.downloads.windows.as_mut(),
+                    v.downloads.mac.as_mut(),
+                    v.downloads.mac_intel.as_mut(),
+                    v.downloads.linux.as_mut(),
+                ]
+            })
+            .flatten()
+    }
+
+    /// Turn every download URL that is a relative path into a `file://` URL
+    /// below `base`.
+    pub fn resolve_relative_urls(&mut self, base: &Path) {
+        for download in self.downloads_mut() {
+            if Url::parse(&download.url).is_ok() {
+                continue;
+            }
+            if let Ok(url) = Url::from_file_path(base.join(&download.url)) {
+                download.url = url.to_string();
+            }
+        }
+    }
+}
+
 /// The available products.
-#[derive(Clone, Debug, Deserialize, Getters)]
-#[getset(get = "pub")]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
 pub struct Product {
     /// A unique, unchanged internal ID for this product
     id: String,
@@ -26,46 +156,207 @@ pub struct Product {
     install_directory: String,
     /// A list of files/directories to remove when upgrading from particular versions
     removals: Vec<Removals>,
+    /// A list of files/directories, relative to the install directory, that hold user data and should survive uninstall and upgrade
+    #[serde(default)]
+    user_data: Vec<String>,
+    /// The oldest installer that can install this product
+    requires_installer: Option<Version>,
+    /// Where to find the version of a copy of this product, so copies the installer didn't put down can be adopted
+    version_probe: Option<VersionProbe>,
     /// A list of available versions
+    #[serde(deserialize_with = "deserialize_versions")]
     versions: Vec<ProductVersion>,
 }
 
 impl Product {
-    /// Calculate the latest version available of this product
-    pub fn latest_version(&self, allow_prerelease: bool) -> Version {
-        let mut latest_version = Version::new(0, 0, 0);
-        for version in self.versions() {
-            let v = version.version();
-            if (allow_prerelease || v.pre.is_empty()) && *v > latest_version {
-                latest_version = v.clone();
-            }
+    /// Find the versions in `channel` within `pin` that aren't yanked,
+    /// optionally only those with a build for `platform`, those this
+    /// installer supports and those whose rollout has reached this machine.
+    fn candidate_versions(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+        platform: Option<Platform>,
+        supported_only: bool,
+        eligible_only: bool,
+    ) -> Vec<&ProductVersion> {
+        self.versions()
+            .iter()
+            .filter(|v| channel.includes(v.version()))
+            .filter(|v| is_within_pin(v.version(), pin))
+            .filter(|v| !v.yanked())
+            .filter(|v| {
+                !eligible_only
+                    || v.rollout()
+                        .as_ref()
+                        .is_none_or(|r| rollout::is_eligible(&self.id, v.version(), r))
+            })
+            .filter(|v| platform.is_none_or(|p| v.downloads().for_platform(p).is_some()))
+            .filter(|v| !supported_only || v.is_supported())
+            .collect()
+    }
+
+    /// Find the newest of the [candidate versions](Self::candidate_versions).
+    fn newest_version(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+        platform: Option<Platform>,
+        supported_only: bool,
+        eligible_only: bool,
+    ) -> Option<&ProductVersion> {
+        self.candidate_versions(channel, pin, platform, supported_only, eligible_only)
+            .into_iter()
+            .max_by(|a, b| a.version().cmp(b.version()))
+    }
+
+    /// Find the latest version of this product in `channel` this installer
+    /// can install on any platform, skipping yanked versions and keeping
+    /// within `pin` if there is one. Rollouts are ignored, so this is the same
+    /// on every machine.
+    pub fn latest_version(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+    ) -> Option<&ProductVersion> {
+        if !self.is_supported() {
+            return None;
         }
-        latest_version
+        self.newest_version(channel, pin, None, true, false)
     }
 
-    pub fn latest_version_data(&self, allow_prerelease: bool) -> Option<DownloadSpec> {
-        for v in self.versions() {
-            if *v.version() == self.latest_version(allow_prerelease) {
-                if cfg!(target_os = "windows") {
-                    return v.downloads().windows().clone();
-                } else if cfg!(target_os = "macos") && cfg!(target_arch = "x86_64") {
-                    return v.downloads().mac_intel().clone();
-                } else if cfg!(target_os = "macos") && cfg!(target_arch = "aarch64") {
-                    return v.downloads().mac().clone();
-                } else if cfg!(target_os = "linux") {
-                    return v.downloads().linux().clone();
-                }
-            }
+    /// Like [`Self::latest_version`], but skipping versions still rolling out
+    /// to other machines.
+    pub fn latest_version_for_this_machine(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+    ) -> Option<&ProductVersion> {
+        if !self.is_supported() {
+            return None;
+        }
+        self.newest_version(channel, pin, None, true, true)
+    }
+
+    /// Choose the version to install on the system we are running on, which
+    /// is the latest version that has a build for it and whose rollout has
+    /// reached this machine, along with that build.
+    pub fn best_version_for_this_system(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+    ) -> Option<(Version, DownloadSpec)> {
+        if !self.is_supported() {
+            return None;
         }
-        None
+        let platform = Platform::current()?;
+        let version = self.newest_version(channel, pin, Some(platform), true, true)?;
+        let download = version.downloads().for_platform(platform)?;
+        Some((version.version().clone(), download))
+    }
+
+    /// Find the version to move to from `installed` once it has been yanked:
+    /// the closest one that can be installed on this system, preferring the
+    /// same minor release, and the newer of two that are equally close.
+    pub fn nearest_version_for_this_system(
+        &self,
+        installed: &Version,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+    ) -> Option<Version> {
+        if !self.is_supported() {
+            return None;
+        }
+        let distance = |v: &Version| {
+            (
+                v.major.abs_diff(installed.major),
+                v.minor.abs_diff(installed.minor),
+                v.patch.abs_diff(installed.patch),
+            )
+        };
+        self.candidate_versions(channel, pin, Some(Platform::current()?), true, true)
+            .into_iter()
+            .map(ProductVersion::version)
+            .filter(|v| *v != installed)
+            .min_by(|a, b| distance(a).cmp(&distance(b)).then(b.cmp(a)))
+            .cloned()
+    }
+
+    /// Get the newest version for this system that `pin` is holding the
+    /// product back from, if there is one.
+    pub fn held_version(&self, channel: &Channel, pin: Option<&VersionReq>) -> Option<Version> {
+        pin?;
+        let (unpinned, _) = self.best_version_for_this_system(channel, None)?;
+        let pinned = self
+            .best_version_for_this_system(channel, pin)
+            .map(|(v, _)| v);
+        (Some(&unpinned) > pinned.as_ref()).then_some(unpinned)
+    }
+
+    /// Can this installer install this product at all?
+    pub fn is_supported(&self) -> bool {
+        is_supported(self.requires_installer.as_ref())
+    }
+
+    /// Get the installer version needed for the newest version of this
+    /// product, if this installer is too old for it. Versions this installer
+    /// can't read don't say which platforms they have builds for, so builds
+    /// for any platform count.
+    pub fn required_installer(
+        &self,
+        channel: &Channel,
+        pin: Option<&VersionReq>,
+    ) -> Option<Version> {
+        if !self.is_supported() {
+            return self.requires_installer.clone();
+        }
+        self.newest_version(channel, pin, None, false, true)
+            .filter(|v| !v.is_supported())
+            .and_then(|v| v.requires_installer.clone())
+    }
+
+    /// Get the versions after `from` up to and including `to`, newest first.
+    pub fn versions_between(
+        &self,
+        from: &Version,
+        to: &Version,
+        channel: &Channel,
+    ) -> Vec<&ProductVersion> {
+        let mut versions: Vec<_> = self
+            .versions()
+            .iter()
+            .filter(|v| v.version() > from && v.version() <= to)
+            .filter(|v| channel.includes(v.version()) || v.version() == to)
+            .collect();
+        versions.sort_by(|a, b| b.version().cmp(a.version()));
+        versions
+    }
+
+    /// Get the download for this system of a particular version
+    pub fn version_data(&self, version: &Version) -> Option<DownloadSpec> {
+        self.versions()
+            .iter()
+            .find(|v| v.version() == version)
+            .and_then(|v| v.downloads().for_this_system())
     }
 }
 
+/// Is `version` allowed by `pin`? Prereleases are judged by the release they
+/// lead up to, so a pin of `2.x` still allows `2.1.0-beta`.
+pub fn is_within_pin(version: &Version, pin: Option<&VersionReq>) -> bool {
+    pin.is_none_or(|pin| {
+        pin.matches(version)
+            || !version.pre.is_empty()
+                && pin.matches(&Version::new(version.major, version.minor, version.patch))
+    })
+}
+
 /// A list of files/directories to remove when upgrading from particular versions
-#[derive(Clone, Debug, Deserialize, Getters)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters)]
 #[getset(get = "pub")]
 pub struct Removals {
     /// Only remove files when upgrading from a version matching this requirement
+    #[schemars(with = "String")]
     on_upgrade_from: VersionReq,
     /// Only remove files from a particular OS
     on: Option<Vec<String>>,
@@ -74,18 +365,181 @@ pub struct Removals {
 }
 
 /// An available version of a product.
-#[derive(Clone, Debug, Deserialize, Getters)]
-#[getset(get = "pub")]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
 pub struct ProductVersion {
     /// Semantic version
     version: Version,
+    /// What changed in this version, either as Markdown or an http(s) URL to the notes
+    release_notes: Option<String>,
+    /// When this version was released, as an ISO 8601 date
+    released_at: Option<String>,
+    /// The oldest installer that can install this version
+    requires_installer: Option<Version>,
+    /// Has this version been withdrawn? Yanked versions are never offered, but can still be repaired
+    #[serde(default)]
+    yanked: bool,
+    /// Why this version was withdrawn
+    yank_reason: Option<String>,
+    /// Release this version gradually rather than to everyone at once
+    rollout: Option<Rollout>,
     /// The downloads for this product
     downloads: ProductDownloads,
 }
 
-/// The downloads
-#[derive(Clone, Debug, Deserialize, Getters)]
+impl ProductVersion {
+    pub fn new(version: Version, downloads: ProductDownloads) -> Self {
+        Self {
+            version,
+            release_notes: None,
+            released_at: None,
+            requires_installer: None,
+            yanked: false,
+            yank_reason: None,
+            rollout: None,
+            downloads,
+        }
+    }
+
+    /// Can this installer install this version?
+    pub fn is_supported(&self) -> bool {
+        is_supported(self.requires_installer.as_ref())
+    }
+
+    /// Get the release notes if they are a link to elsewhere.
+    pub fn release_notes_url(&self) -> Option<&str> {
+        self.release_notes
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| n.starts_with("http://") || n.starts_with("https://"))
+            .filter(|n| !n.contains(char::is_whitespace))
+    }
+}
+
+/// Where to find which version of a product is in a directory
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters)]
+#[getset(get = "pub")]
+pub struct VersionProbe {
+    /// The file holding the version, relative to the install directory
+    file: String,
+    /// A regular expression matching the version in the file, with the version in its first group. Defaults to the first thing that looks like a version
+    pattern: Option<String>,
+}
+
+/// A release channel, such as stable, beta or nightly. Releases without a
+/// pre-release tag are in every channel.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters)]
+#[getset(get = "pub")]
+pub struct Channel {
+    /// A unique ID for this channel, which is saved for each product
+    id: String,
+    /// The name of the channel to show to users
+    name: String,
+    /// The pre-release identifiers in this channel, matched against the first part of the pre-release tag, so `beta` includes `2.0.0-beta.3`. `*` includes every pre-release
+    #[serde(default)]
+    prerelease: Vec<String>,
+    /// Pre-release versions that are in this channel regardless of their tag
+    #[serde(default)]
+    versions: Vec<Version>,
+}
+
+impl Channel {
+    /// The channel with only full releases in it
+    fn stable() -> Self {
+        Self {
+            id: "stable".to_string(),
+            name: "Stable".to_string(),
+            prerelease: vec![],
+            versions: vec![],
+        }
+    }
+
+    /// The channels used when the manifest doesn't list any, which match the
+    /// behaviour from before there were channels
+    fn defaults() -> Vec<Self> {
+        vec![
+            Self::stable(),
+            Self {
+                id: "prerelease".to_string(),
+                name: "Prerelease".to_string(),
+                prerelease: vec!["*".to_string()],
+                versions: vec![],
+            },
+        ]
+    }
+
+    /// Does this channel have any pre-releases in it?
+    pub fn has_prereleases(&self) -> bool {
+        !self.prerelease.is_empty() || !self.versions.is_empty()
+    }
+
+    /// Is `version` in this channel?
+    pub fn includes(&self, version: &Version) -> bool {
+        if version.pre.is_empty() || self.versions.contains(version) {
+            return true;
+        }
+        let tag = version.pre.as_str().split('.').next().unwrap_or_default();
+        self.prerelease.iter().any(|p| p == "*" || p == tag)
+    }
+}
+
+/// A gradual release of a version to a share of machines
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters)]
 #[getset(get = "pub")]
+pub struct Rollout {
+    /// The percentage of machines, from 0 to 100, offered this version
+    percentage: u8,
+    /// When to start offering this version, as an RFC 3339 date and time such as `2025-01-31T09:00:00Z`
+    start: Option<String>,
+}
+
+/// Read a product's versions, skipping any this installer can't understand
+/// rather than failing to read the whole manifest. Versions that say which
+/// installer they need are kept without their downloads, so the user can be
+/// told to update.
+fn deserialize_versions<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ProductVersion>, D::Error> {
+    #[derive(Deserialize)]
+    struct UnreadableVersion {
+        version: Version,
+        requires_installer: Option<Version>,
+    }
+
+    let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match ProductVersion::deserialize(&value) {
+            Ok(version) => Some(version),
+            Err(e) => match UnreadableVersion::deserialize(&value) {
+                Ok(UnreadableVersion {
+                    version,
+                    requires_installer: Some(required),
+                }) if !is_supported(Some(&required)) => {
+                    tracing::info!("v{version} needs installer v{required}, so it can't be read");
+                    Some(ProductVersion {
+                        version,
+                        release_notes: None,
+                        released_at: None,
+                        requires_installer: Some(required),
+                        yanked: false,
+                        yank_reason: None,
+                        rollout: None,
+                        downloads: ProductDownloads::default(),
+                    })
+                }
+                _ => {
+                    tracing::warn!("Skipping a product version that can't be read: {e}");
+                    None
+                }
+            },
+        })
+        .collect())
+}
+
+/// The downloads
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
 pub struct ProductDownloads {
     /// The Windows download
     windows: Option<DownloadSpec>,
@@ -98,22 +552,140 @@ pub struct ProductDownloads {
     linux: Option<DownloadSpec>,
 }
 
+impl ProductDownloads {
+    /// Get the download that matches the system we are running on
+    pub fn for_this_system(&self) -> Option<DownloadSpec> {
+        Platform::current().and_then(|p| self.for_platform(p))
+    }
+
+    /// Get the download for a particular platform
+    pub fn for_platform(&self, platform: Platform) -> Option<DownloadSpec> {
+        match platform {
+            Platform::Windows => self.windows().clone(),
+            Platform::Mac => self.mac().clone(),
+            Platform::MacIntel => self.mac_intel().clone(),
+            Platform::Linux => self.linux().clone(),
+        }
+    }
+
+    /// Set the download for a particular platform
+    pub fn set_for_platform(&mut self, platform: Platform, download: Option<DownloadSpec>) {
+        match platform {
+            Platform::Windows => self.windows = download,
+            Platform::Mac => self.mac = download,
+            Platform::MacIntel => self.mac_intel = download,
+            Platform::Linux => self.linux = download,
+        }
+    }
+}
+
+/// The platforms that products can be downloaded for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Windows,
+    Mac,
+    MacIntel,
+    Linux,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::Windows,
+        Platform::Mac,
+        Platform::MacIntel,
+        Platform::Linux,
+    ];
+
+    /// The platform we are running on, if it is supported
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "windows") {
+            Some(Platform::Windows)
+        } else if cfg!(target_os = "macos") && cfg!(target_arch = "x86_64") {
+            Some(Platform::MacIntel)
+        } else if cfg!(target_os = "macos") && cfg!(target_arch = "aarch64") {
+            Some(Platform::Mac)
+        } else if cfg!(target_os = "linux") {
+            Some(Platform::Linux)
+        } else {
+            None
+        }
+    }
+
+    /// The name used for this platform in the manifest
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Windows => "windows",
+            Platform::Mac => "mac",
+            Platform::MacIntel => "mac-intel",
+            Platform::Linux => "linux",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or(format!(
+                "unknown platform {s:?}, expected one of windows, mac, mac-intel or linux"
+            ))
+    }
+}
+
 /// The specification of the download
-#[derive(Clone, Debug, Deserialize, Getters)]
-#[getset(get = "pub")]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, Getters, Setters)]
+#[getset(get = "pub", set = "pub")]
 pub struct DownloadSpec {
     /// The URL to download the data from
     url: String,
+    /// Mirrors to try, in order, if the main URL can't be downloaded from
+    #[serde(default)]
+    mirrors: Vec<String>,
+    /// The hex encoded SHA-256 hash of the download
+    sha256: Option<String>,
     /// The download/install strategy
     strategy: DownloadStrategy,
     /// The relative path to the executable to start this product, if it can be started.
     executable: Option<String>,
     /// The absolute path to the executable to start this product, if it can be started.
     executable_absolute: Option<String>,
+    /// The size of the download in bytes
+    size: Option<u64>,
+    /// The size of the product once installed in bytes
+    installed_size: Option<u64>,
+}
+
+impl DownloadSpec {
+    pub fn new(url: String, strategy: DownloadStrategy) -> Self {
+        Self {
+            url,
+            mirrors: vec![],
+            sha256: None,
+            strategy,
+            executable: None,
+            executable_absolute: None,
+            size: None,
+            installed_size: None,
+        }
+    }
+
+    /// All of the URLs this download is available from, in the order they should be tried
+    pub fn sources(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(self.url()).chain(self.mirrors())
+    }
 }
 
 /// The possible download and install strategies
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub enum DownloadStrategy {
     /// Download a single file. This file should remain unprocessed in the target directory
     File {
@@ -129,3 +701,200 @@ pub enum DownloadStrategy {
     /// Download a gzip compressed tarball file. This file should be uncompressed in the target directory, flattening if needed
     GzippedTarball,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Make a product with a build of each version for every platform.
+    fn product(versions: Value) -> Product {
+        let download = json!({ "url": "https://example.com/p.zip", "strategy": "ZipFile" });
+        let versions: Vec<_> = versions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| {
+                let mut v = v.clone();
+                v["downloads"] = json!({
+                    "windows": download,
+                    "mac": download,
+                    "mac-intel": download,
+                    "linux": download,
+                });
+                v
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": "p",
+            "name": "P",
+            "description": "",
+            "install_directory": "p",
+            "removals": [],
+            "versions": versions,
+        }))
+        .unwrap()
+    }
+
+    fn channel(prerelease: &[&str], versions: &[&str]) -> Channel {
+        serde_json::from_value(json!({
+            "id": "test",
+            "name": "Test",
+            "prerelease": prerelease,
+            "versions": versions,
+        }))
+        .unwrap()
+    }
+
+    fn version(v: &str) -> Version {
+        Version::parse(v).unwrap()
+    }
+
+    fn latest(prod: &Product, channel: &Channel, pin: Option<&str>) -> Option<Version> {
+        let pin = pin.map(|p| VersionReq::parse(p).unwrap());
+        prod.latest_version(channel, pin.as_ref())
+            .map(|v| v.version().clone())
+    }
+
+    #[test]
+    fn pins_judge_prereleases_by_their_release() {
+        let pin = VersionReq::parse("2.x").unwrap();
+        assert!(is_within_pin(&version("2.1.0"), Some(&pin)));
+        assert!(is_within_pin(&version("2.1.0-beta.1"), Some(&pin)));
+        assert!(!is_within_pin(&version("3.0.0-beta.1"), Some(&pin)));
+        assert!(!is_within_pin(&version("1.9.0"), Some(&pin)));
+        assert!(is_within_pin(&version("3.0.0"), None));
+    }
+
+    #[test]
+    fn latest_version_keeps_within_pin() {
+        let prod = product(json!([
+            { "version": "1.0.0" },
+            { "version": "1.1.0" },
+            { "version": "2.0.0" },
+        ]));
+        let stable = Channel::stable();
+        assert_eq!(latest(&prod, &stable, None), Some(version("2.0.0")));
+        assert_eq!(latest(&prod, &stable, Some("1.x")), Some(version("1.1.0")));
+        assert_eq!(latest(&prod, &stable, Some("3.x")), None);
+    }
+
+    #[test]
+    fn pins_report_the_version_held_back() {
+        let prod = product(json!([{ "version": "1.1.0" }, { "version": "2.0.0" }]));
+        let stable = Channel::stable();
+        let pin = VersionReq::parse("1.x").unwrap();
+        assert_eq!(
+            prod.held_version(&stable, Some(&pin)),
+            Some(version("2.0.0"))
+        );
+        assert_eq!(prod.held_version(&stable, None), None);
+    }
+
+    #[test]
+    fn latest_version_skips_yanked_versions() {
+        let prod = product(json!([
+            { "version": "1.0.0" },
+            { "version": "1.1.0", "yanked": true },
+        ]));
+        assert_eq!(
+            latest(&prod, &Channel::stable(), None),
+            Some(version("1.0.0"))
+        );
+    }
+
+    #[test]
+    fn nearest_version_prefers_the_same_minor_release() {
+        let prod = product(json!([
+            { "version": "2.0.0" },
+            { "version": "2.0.1", "yanked": true },
+            { "version": "2.0.2" },
+            { "version": "3.0.0" },
+        ]));
+        let stable = Channel::stable();
+        let nearest = prod.nearest_version_for_this_system(&version("2.0.1"), &stable, None);
+        assert_eq!(nearest, Some(version("2.0.2")));
+
+        let prod = product(json!([
+            { "version": "2.0.0" },
+            { "version": "2.0.1", "yanked": true },
+            { "version": "3.0.0" },
+        ]));
+        let nearest = prod.nearest_version_for_this_system(&version("2.0.1"), &stable, None);
+        assert_eq!(nearest, Some(version("2.0.0")));
+    }
+
+    #[test]
+    fn rollouts_only_hold_back_this_machine() {
+        let prod = product(json!([
+            { "version": "1.0.0" },
+            { "version": "1.1.0", "rollout": { "percentage": 100, "start": "2999-01-01T00:00:00Z" } },
+        ]));
+        let stable = Channel::stable();
+        // Validation and bundles see the same newest version everywhere
+        assert_eq!(latest(&prod, &stable, None), Some(version("1.1.0")));
+        let best = prod.best_version_for_this_system(&stable, None);
+        assert_eq!(best.map(|(v, _)| v), Some(version("1.0.0")));
+    }
+
+    #[test]
+    fn channels_include_releases_and_their_prereleases() {
+        let stable = Channel::stable();
+        assert!(stable.includes(&version("2.0.0")));
+        assert!(!stable.includes(&version("2.0.0-beta.1")));
+
+        let beta = channel(&["beta"], &["2.0.0-rc.1"]);
+        assert!(beta.includes(&version("2.0.0")));
+        assert!(beta.includes(&version("2.0.0-beta.3")));
+        assert!(beta.includes(&version("2.0.0-rc.1")));
+        assert!(!beta.includes(&version("2.0.0-rc.2")));
+        assert!(!beta.includes(&version("2.0.0-alpha.1")));
+
+        let everything = channel(&["*"], &[]);
+        assert!(everything.includes(&version("2.0.0-alpha.1")));
+    }
+
+    #[test]
+    fn latest_version_follows_the_channel() {
+        let prod = product(json!([{ "version": "2.0.0" }, { "version": "2.1.0-beta.1" }]));
+        let beta = channel(&["beta"], &[]);
+        assert_eq!(
+            latest(&prod, &Channel::stable(), None),
+            Some(version("2.0.0"))
+        );
+        assert_eq!(latest(&prod, &beta, None), Some(version("2.1.0-beta.1")));
+    }
+
+    #[test]
+    fn unreadable_versions_ask_for_a_newer_installer() {
+        let download = json!({ "url": "https://example.com/p.zip", "strategy": "ZipFile" });
+        let teleport = json!({ "url": "https://example.com/p", "strategy": "Teleport" });
+        let prod: Product = serde_json::from_value(json!({
+            "id": "p",
+            "name": "P",
+            "description": "",
+            "install_directory": "p",
+            "removals": [],
+            "versions": [
+                {
+                    "version": "1.0.0",
+                    "downloads": { "windows": download, "mac": download, "mac-intel": download, "linux": download },
+                },
+                {
+                    "version": "2.0.0",
+                    "requires_installer": "999.0.0",
+                    "downloads": { "windows": teleport, "mac": teleport, "mac-intel": teleport, "linux": teleport },
+                },
+            ],
+        }))
+        .unwrap();
+        let stable = Channel::stable();
+        assert_eq!(
+            prod.required_installer(&stable, None),
+            Some(version("999.0.0"))
+        );
+        let best = prod.best_version_for_this_system(&stable, None);
+        assert_eq!(best.map(|(v, _)| v), Some(version("1.0.0")));
+    }
+}
@@ -0,0 +1,175 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The file that turns on portable mode when it is next to the installer
+pub const PORTABLE_MARKER: &str = "angelsuite-portable";
+
+/// Get the directory given with `--root` or `ANGELSUITE_ROOT` to keep
+/// everything in, if there is one. The arguments are read directly, as logging
+/// starts before the command line is parsed.
+pub fn root_override() -> Option<PathBuf> {
+    static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        root_argument(env::args_os().skip(1))
+            .or_else(|| env::var_os("ANGELSUITE_ROOT"))
+            .filter(|root| !root.is_empty())
+            .map(PathBuf::from)
+            .map(|root| std::path::absolute(&root).unwrap_or(root))
+    })
+    .clone()
+}
+
+fn root_argument(mut args: impl Iterator<Item = OsString>) -> Option<OsString> {
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--root" {
+            return args.next();
+        } else if let Some(root) = arg.to_str().and_then(|a| a.strip_prefix("--root=")) {
+            return Some(root.into());
+        }
+    }
+    None
+}
+
+/// Get the directory the installer executable is in. AppImages run from
+/// where they are mounted, so the directory of the image itself is used.
+fn executable_dir() -> PathBuf {
+    env::var_os("APPIMAGE")
+        .map(PathBuf::from)
+        .or_else(|| env::current_exe().ok())
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or(PathBuf::from("."))
+}
+
+/// Get the directory to keep everything in if running in portable mode,
+/// which is turned on by an `angelsuite-portable` file next to the installer
+/// or by `ANGELSUITE_PORTABLE`.
+pub fn portable_dir() -> Option<PathBuf> {
+    static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PORTABLE_DIR
+        .get_or_init(|| {
+            let dir = executable_dir();
+            let portable = env::var("ANGELSUITE_PORTABLE").is_ok_and(|v| !v.is_empty())
+                || dir.join(PORTABLE_MARKER).exists();
+            portable.then_some(dir)
+        })
+        .clone()
+}
+
+/// On Windows, everything is kept next to the executable if that directory is
+/// writeable, or in an appdata folder if not, as we were probably installed
+/// as admin.
+#[cfg(target_os = "windows")]
+fn default_shared_dir() -> Option<PathBuf> {
+    let exe_dir = executable_dir();
+    let test_file = exe_dir.join(".testio");
+    let writeable = fs::write(&test_file, []).is_ok();
+    let _ = fs::remove_file(&test_file);
+    tracing::debug!("Executable directory is writeable: {writeable}");
+
+    if writeable {
+        Some(exe_dir)
+    } else {
+        Some(user_dir(dirs::data_local_dir(), "AngelSuite"))
+    }
+}
+
+/// Elsewhere, state, installs and cache are kept in the user's directories for each.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn default_shared_dir() -> Option<PathBuf> {
+    None
+}
+
+/// Get the one directory to keep everything in, if everything is kept together.
+fn shared_dir() -> Option<PathBuf> {
+    static SHARED_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    SHARED_DIR
+        .get_or_init(|| {
+            let dir = root_override()
+                .or_else(portable_dir)
+                .or_else(default_shared_dir)?;
+            create_dir(&dir);
+            Some(dir)
+        })
+        .clone()
+}
+
+/// Get one of the user's directories, falling back to their home directory or
+/// the working directory if it can't be found.
+fn user_base(base: Option<PathBuf>) -> PathBuf {
+    base.or_else(dirs::home_dir).unwrap_or_else(|| {
+        tracing::warn!("Couldn't find a home directory, using the working directory");
+        PathBuf::from(".")
+    })
+}
+
+/// Get the directory `name` below one of the user's directories.
+fn user_dir(base: Option<PathBuf>, name: &str) -> PathBuf {
+    let dir = user_base(base).join(name);
+    create_dir(&dir);
+    dir
+}
+
+fn create_dir(dir: &Path) {
+    if let Err(e) = fs::create_dir_all(dir) {
+        tracing::warn!("Failed to create {dir:?}: {e}");
+    }
+}
+
+/// Get a file the installer keeps: `name` in the shared directory if
+/// everything is kept together, otherwise `user_path` below `base`.
+fn file(name: &str, base: Option<PathBuf>, user_path: &str) -> PathBuf {
+    match shared_dir() {
+        Some(dir) => dir.join(name),
+        None => {
+            let path = user_base(base).join(user_path);
+            if let Some(parent) = path.parent() {
+                create_dir(parent);
+            }
+            path
+        }
+    }
+}
+
+/// The directory products are installed in by default.
+pub fn local_install_dir() -> PathBuf {
+    shared_dir().unwrap_or_else(|| user_dir(dirs::data_local_dir(), "angelsuite"))
+}
+
+/// The directory logs are written to.
+pub fn local_log_dir() -> PathBuf {
+    local_install_dir()
+}
+
+/// The file recording installed products and settings.
+pub fn local_install_file() -> PathBuf {
+    file("installer.json", dirs::config_dir(), "angelsuite.json")
+}
+
+/// The file of environment variables products are started with.
+pub fn local_environment_file() -> PathBuf {
+    file(".env", dirs::config_dir(), "angelsuite.env")
+}
+
+/// The file holding this machine's rollout ID.
+pub fn local_machine_id_file() -> PathBuf {
+    file(".machine-id", dirs::config_dir(), "angelsuite-machine-id")
+}
+
+/// The file the last fetched manifest is cached in.
+pub fn local_manifest_cache_file() -> PathBuf {
+    file(
+        "manifest-cache.json",
+        dirs::cache_dir(),
+        "angelsuite/manifest.json",
+    )
+}
+
+/// The directory that user data is moved into while a product is uninstalled.
+pub fn local_user_data_backup_dir(product_id: &str) -> PathBuf {
+    local_install_dir().join(".userdata").join(product_id)
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::install::Install;
+use crate::manifest::{Channel, Manifest};
+
+/// Parts of environment variable names that suggest they hold a secret, which
+/// are left out of exported profiles. These match anywhere in a name, so
+/// `DBPASSWORD` is left out too, at the cost of some names that aren't
+/// secret, such as `MONKEY_MODE`.
+const SECRET_NAMES: [&str; 8] = [
+    "SECRET",
+    "TOKEN",
+    "PASS",
+    "PWD",
+    "KEY",
+    "CREDENTIAL",
+    "AUTH",
+    "PRIVATE",
+];
+
+/// A shareable set of products to install, and how to keep them updated
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// The products in the profile, by ID
+    pub products: BTreeMap<String, ProfileProduct>,
+    /// Environment variables to start products with
+    #[serde(default)]
+    pub environment: BTreeMap<String, String>,
+}
+
+/// How a product in a profile is installed
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProfileProduct {
+    /// The release channel to follow, or the most stable one if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// The range of versions to hold the product within
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<VersionReq>,
+    /// The exact version to install, which holds the product at that version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+}
+
+impl ProfileProduct {
+    /// Find the channel to follow in `manifest`.
+    fn resolve_channel(&self, manifest: &Manifest) -> Result<Channel, String> {
+        match &self.channel {
+            Some(id) => manifest
+                .available_channels()
+                .into_iter()
+                .find(|c| c.id() == id)
+                .ok_or(format!("no channel with ID {id}")),
+            None => Ok(manifest.channel_for(None, false)),
+        }
+    }
+
+    /// Get the range of versions the product is held within, if any.
+    pub fn effective_pin(&self) -> Option<VersionReq> {
+        match &self.version {
+            Some(version) => VersionReq::parse(&format!("={version}")).ok(),
+            None => self.pin.clone(),
+        }
+    }
+}
+
+/// Something to do to make this machine match a profile
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Install {
+        id: String,
+        name: String,
+        version: String,
+    },
+    Update {
+        id: String,
+        name: String,
+        from: String,
+        to: String,
+    },
+    Downgrade {
+        id: String,
+        name: String,
+        from: String,
+        to: String,
+    },
+    Remove {
+        id: String,
+        name: String,
+        version: String,
+    },
+}
+
+/// A change to the channel or pin of a product to match a profile
+#[derive(Clone, Debug, Serialize)]
+pub struct SettingChange {
+    pub id: String,
+    /// The ID of the channel the product will follow
+    pub channel: String,
+    /// The range of versions the product will be held within, if any
+    pub pin: Option<String>,
+}
+
+/// What it would take to make this machine match a profile
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+    /// Parts of the profile that can't be matched
+    pub problems: Vec<String>,
+}
+
+impl Profile {
+    /// Read a profile from a file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read {path:?}: {e}"))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| format!("{} isn't a valid profile: {e}", path.display()))
+    }
+
+    /// Write this profile to a file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        fs::write(path, json).map_err(|e| format!("Failed to write {path:?}: {e}"))
+    }
+
+    /// Describe what is installed, along with the environment variables that
+    /// don't look secret. If `exact`, everyone using the profile gets the same
+    /// versions, rather than the latest in each channel. Channels are always
+    /// given, so products that follow prereleases from before there were
+    /// channels keep doing so.
+    pub fn from_install(
+        install: &Install,
+        manifest: &Manifest,
+        environment: BTreeMap<String, String>,
+        exact: bool,
+    ) -> Self {
+        let products = install
+            .products()
+            .iter()
+            .filter_map(|(id, prod)| {
+                let version = prod.version().as_ref()?;
+                Some((
+                    id.clone(),
+                    ProfileProduct {
+                        channel: Some(
+                            manifest
+                                .channel_for(prod.channel().as_deref(), *prod.use_prerelease())
+                                .id()
+                                .clone(),
+                        ),
+                        pin: prod.pin().clone(),
+                        version: Version::parse(version).ok().filter(|_| exact),
+                    },
+                ))
+            })
+            .collect();
+        let environment = environment
+            .into_iter()
+            .filter(|(key, _)| !looks_secret(key))
+            .collect();
+        Self {
+            products,
+            environment,
+        }
+    }
+
+    /// Work out what to install, update, downgrade and remove to match this
+    /// profile, as if its channels and pins were already chosen.
+    pub fn plan(&self, manifest: &Manifest, install: &Install) -> Plan {
+        let mut plan = Plan::default();
+        for (id, wanted) in &self.products {
+            let Some(prod) = manifest.products().iter().find(|p| p.id() == id) else {
+                plan.problems
+                    .push(format!("{id} isn't in the product list"));
+                continue;
+            };
+            let channel = match wanted.resolve_channel(manifest) {
+                Ok(channel) => channel,
+                Err(e) => {
+                    plan.problems.push(format!("{}: {e}", prod.name()));
+                    continue;
+                }
+            };
+            let Some((version, _)) =
+                prod.best_version_for_this_system(&channel, wanted.effective_pin().as_ref())
+            else {
+                plan.problems.push(format!(
+                    "{} has no version for this system that matches the profile",
+                    prod.name()
+                ));
+                continue;
+            };
+            let local = install.products().get(id).and_then(|p| p.version().clone());
+            match local {
+                None => plan.actions.push(Action::Install {
+                    id: id.clone(),
+                    name: prod.name().clone(),
+                    version: version.to_string(),
+                }),
+                Some(local) if local == version.to_string() => (),
+                Some(local) if Version::parse(&local).is_ok_and(|l| l > version) => {
+                    plan.actions.push(Action::Downgrade {
+                        id: id.clone(),
+                        name: prod.name().clone(),
+                        from: local,
+                        to: version.to_string(),
+                    })
+                }
+                Some(local) => plan.actions.push(Action::Update {
+                    id: id.clone(),
+                    name: prod.name().clone(),
+                    from: local,
+                    to: version.to_string(),
+                }),
+            }
+        }
+        for (id, prod) in install.products() {
+            if let Some(version) = prod.version() {
+                if !self.products.contains_key(id) {
+                    plan.actions.push(Action::Remove {
+                        id: id.clone(),
+                        name: prod.name().clone(),
+                        version: version.clone(),
+                    });
+                }
+            }
+        }
+        plan
+    }
+
+    /// Find the products whose channel or pin doesn't match this profile.
+    pub fn setting_changes(&self, manifest: &Manifest, install: &Install) -> Vec<SettingChange> {
+        self.products
+            .iter()
+            .filter_map(|(id, wanted)| {
+                let channel = wanted.resolve_channel(manifest).ok()?;
+                let pin = wanted.effective_pin();
+                let current = install.products().get(id);
+                let same = current
+                    .is_some_and(|p| p.channel().as_ref() == Some(channel.id()) && *p.pin() == pin);
+                (!same).then(|| SettingChange {
+                    id: id.clone(),
+                    channel: channel.id().clone(),
+                    pin: pin.map(|p| p.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// Choose the channels and pins from this profile for its products.
+    pub fn apply_settings(&self, manifest: &Manifest, install: &mut Install) {
+        for (id, wanted) in &self.products {
+            let Ok(channel) = wanted.resolve_channel(manifest) else {
+                continue;
+            };
+            let prod = install.get_mut_product_or_default(id.clone());
+            prod.set_channel(Some(channel.id().clone()));
+            prod.set_use_prerelease(channel.has_prereleases());
+            prod.set_pin(wanted.effective_pin());
+        }
+    }
+}
+
+/// Does the environment variable `name` look like it holds a secret?
+fn looks_secret(name: &str) -> bool {
+    let name = name.to_uppercase();
+    SECRET_NAMES.iter().any(|s| name.contains(s))
+}
+
+/// Read the environment variables products are started with.
+pub fn read_environment(path: &Path) -> BTreeMap<String, String> {
+    dotenvy::from_path_iter(path)
+        .map(|iter| iter.flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Set environment variables in the file products are started with, keeping
+/// any others already there. Comments in the file aren't kept.
+pub fn write_environment(path: &Path, values: &BTreeMap<String, String>) -> io::Result<()> {
+    if values.is_empty() {
+        return Ok(());
+    }
+    let mut environment = read_environment(path);
+    environment.extend(values.clone());
+    let contents: String = environment
+        .iter()
+        .map(|(key, value)| {
+            // Single quoted values are taken literally, so only fall back to escaping if needed
+            if value.contains('\'') || value.contains('\n') {
+                let value = value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('$', "\\$")
+                    .replace('\n', "\\n");
+                format!("{key}=\"{value}\"\n")
+            } else {
+                format!("{key}='{value}'\n")
+            }
+        })
+        .collect();
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn manifest(ids: &[&str]) -> Manifest {
+        let download = json!({ "url": "https://example.com/p.zip", "strategy": "ZipFile" });
+        let downloads = json!({
+            "windows": download,
+            "mac": download,
+            "mac-intel": download,
+            "linux": download,
+        });
+        let products: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "name": id.to_uppercase(),
+                    "description": "",
+                    "install_directory": id,
+                    "removals": [],
+                    "versions": [
+                        { "version": "1.0.0", "downloads": downloads },
+                        { "version": "2.0.0", "downloads": downloads },
+                    ],
+                })
+            })
+            .collect();
+        Manifest::parse(json!({ "products": products }).to_string().as_bytes()).unwrap()
+    }
+
+    fn install(versions: &[(&str, &str)]) -> Install {
+        let products: serde_json::Map<String, Value> = versions
+            .iter()
+            .map(|(id, version)| {
+                let prod = json!({ "name": id.to_uppercase(), "version": version, "use_prerelease": false });
+                (id.to_string(), prod)
+            })
+            .collect();
+        serde_json::from_value(json!({ "products": products })).unwrap()
+    }
+
+    fn profile(products: Value) -> Profile {
+        serde_json::from_value(json!({ "products": products })).unwrap()
+    }
+
+    #[test]
+    fn plan_reaches_the_profile() {
+        let manifest = manifest(&["new", "old", "newer", "extra"]);
+        let install = install(&[("old", "1.0.0"), ("newer", "2.0.0"), ("extra", "1.0.0")]);
+        let profile = profile(json!({
+            "new": {},
+            "old": {},
+            "newer": { "version": "1.0.0" },
+            "unknown": {},
+        }));
+
+        let plan = profile.plan(&manifest, &install);
+
+        let actions = serde_json::to_value(&plan.actions).unwrap();
+        assert_eq!(
+            actions,
+            json!([
+                { "action": "install", "id": "new", "name": "NEW", "version": "2.0.0" },
+                { "action": "downgrade", "id": "newer", "name": "NEWER", "from": "2.0.0", "to": "1.0.0" },
+                { "action": "update", "id": "old", "name": "OLD", "from": "1.0.0", "to": "2.0.0" },
+                { "action": "remove", "id": "extra", "name": "EXTRA", "version": "1.0.0" },
+            ])
+        );
+        assert_eq!(plan.problems, ["unknown isn't in the product list"]);
+    }
+
+    #[test]
+    fn plan_reports_unknown_channels() {
+        let manifest = manifest(&["p"]);
+        let profile = profile(json!({ "p": { "channel": "nightly" } }));
+        let plan = profile.plan(&manifest, &install(&[]));
+        assert!(plan.actions.is_empty());
+        assert_eq!(plan.problems, ["P: no channel with ID nightly"]);
+    }
+
+    #[test]
+    fn plan_is_empty_once_matched() {
+        let manifest = manifest(&["latest", "held"]);
+        let mut install = install(&[("latest", "2.0.0"), ("held", "1.0.0")]);
+        let profile = profile(json!({ "latest": {}, "held": { "pin": "1.x" } }));
+
+        assert!(!profile.setting_changes(&manifest, &install).is_empty());
+        profile.apply_settings(&manifest, &mut install);
+
+        let plan = profile.plan(&manifest, &install);
+        assert!(plan.actions.is_empty());
+        assert!(plan.problems.is_empty());
+        assert!(profile.setting_changes(&manifest, &install).is_empty());
+    }
+
+    #[test]
+    fn secrets_are_matched_anywhere_in_names() {
+        for name in [
+            "API_KEY",
+            "github_token",
+            "SECRET",
+            "DBPASSWORD",
+            "APITOKEN",
+            "GITHUBTOKEN",
+            "AWSSECRETACCESSKEY",
+            "SMTP_PWD",
+        ] {
+            assert!(looks_secret(name), "{name}");
+        }
+        assert!(!looks_secret("LOG_LEVEL"));
+        assert!(!looks_secret("GAME_DIRECTORY"));
+    }
+}
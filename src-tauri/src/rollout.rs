@@ -0,0 +1,140 @@
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use semver::Version;
+use sha2::{Digest, Sha256};
+
+use crate::install::Install;
+use crate::manifest::Rollout;
+
+/// Get this machine's rollout ID, generating and saving one the first time.
+/// It is random rather than derived from the hardware, so it says nothing
+/// about the machine. There is none if no random ID could be generated, as a
+/// fixed one would put every such machine in the same bucket.
+fn machine_id() -> Option<&'static str> {
+    static MACHINE_ID: OnceLock<Option<String>> = OnceLock::new();
+    MACHINE_ID
+        .get_or_init(|| {
+            let path = super::local_machine_id_file();
+            if let Ok(id) = fs::read_to_string(&path) {
+                let id = id.trim();
+                if !id.is_empty() {
+                    return Some(id.to_string());
+                }
+            }
+
+            let mut bytes = [0; 16];
+            if let Err(e) = getrandom::fill(&mut bytes) {
+                tracing::warn!("Failed to generate machine ID, only complete rollouts apply: {e}");
+                return None;
+            }
+            let id: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            if let Err(e) = fs::write(&path, &id) {
+                tracing::warn!("Failed to save machine ID: {e}");
+            }
+            Some(id)
+        })
+        .as_deref()
+}
+
+/// The `early_access` setting from the install data in use
+static EARLY_ACCESS: AtomicBool = AtomicBool::new(false);
+
+/// Keep the `early_access` setting in step with the install data, whenever it
+/// is loaded or changed.
+pub fn follow_setting(install: &Install) {
+    EARLY_ACCESS.store(*install.early_access(), Ordering::Relaxed);
+}
+
+/// Has the user opted in to get versions before their rollout reaches them,
+/// through `ANGELSUITE_EARLY_ACCESS` or the `early_access` setting?
+pub fn early_access() -> bool {
+    env::var("ANGELSUITE_EARLY_ACCESS").is_ok_and(|v| !v.is_empty())
+        || EARLY_ACCESS.load(Ordering::Relaxed)
+}
+
+/// Is this machine included in a version's rollout yet?
+///
+/// Each machine falls in a bucket from 0 to 99 for each product version, so
+/// raising the percentage only ever adds machines, and different releases
+/// reach different machines first.
+pub fn is_eligible(product_id: &str, version: &Version, rollout: &Rollout) -> bool {
+    if early_access() {
+        return true;
+    }
+    if let Some(start) = rollout.start() {
+        match humantime::parse_rfc3339_weak(start) {
+            Ok(start) if SystemTime::now() < start => return false,
+            Ok(_) => (),
+            Err(e) => {
+                tracing::warn!(
+                    "Ignoring invalid rollout start {start:?} for {product_id} v{version}: {e}"
+                );
+            }
+        }
+    }
+    let percentage = u64::from(*rollout.percentage());
+    match machine_id() {
+        Some(id) => bucket(id, product_id, version) < percentage,
+        None => percentage >= 100,
+    }
+}
+
+fn bucket(machine_id: &str, product_id: &str, version: &Version) -> u64 {
+    let hash = Sha256::digest(format!("{machine_id}:{product_id}:{version}"));
+    let mut prefix = [0; 8];
+    prefix.copy_from_slice(&hash[..8]);
+    u64::from_be_bytes(prefix) % 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollout(percentage: u8, start: Option<&str>) -> Rollout {
+        serde_json::from_value(serde_json::json!({ "percentage": percentage, "start": start }))
+            .unwrap()
+    }
+
+    #[test]
+    fn bucket_is_stable() {
+        let version = Version::new(1, 2, 3);
+        let first = bucket("machine", "product", &version);
+        assert_eq!(first, bucket("machine", "product", &version));
+        // Changing how buckets are worked out would reshuffle every rollout in progress
+        assert_eq!(first, 38);
+    }
+
+    #[test]
+    fn buckets_are_spread_across_machines() {
+        let version = Version::new(1, 0, 0);
+        let buckets: Vec<_> = (0..1000)
+            .map(|i| bucket(&format!("machine-{i}"), "product", &version))
+            .collect();
+        assert!(buckets.iter().all(|&b| b < 100));
+        let in_first_half = buckets.iter().filter(|&&b| b < 50).count();
+        assert!((400..600).contains(&in_first_half), "{in_first_half}");
+    }
+
+    #[test]
+    fn buckets_differ_between_releases() {
+        let (v1, v2) = (Version::new(1, 0, 0), Version::new(2, 0, 0));
+        assert!((0..100).any(|i| {
+            let machine = format!("machine-{i}");
+            bucket(&machine, "product", &v1) != bucket(&machine, "product", &v2)
+        }));
+    }
+
+    #[test]
+    fn rollouts_wait_for_their_start() {
+        let version = Version::new(1, 0, 0);
+        let rollout = rollout(100, Some("2999-01-01T00:00:00Z"));
+        assert!(!is_eligible("product", &version, &rollout));
+    }
+}
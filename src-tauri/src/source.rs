@@ -0,0 +1,218 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use semver::Version;
+use tauri::Url;
+
+use crate::bundle::{self, Bundle};
+use crate::cache::{self, ManifestCache};
+use crate::http;
+use crate::manifest::{Manifest, ManifestError};
+
+/// How long a local manifest has to be left alone after changing before it is reloaded
+const RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+/// Where the installer gets its manifest from
+pub enum ManifestSource {
+    /// A manifest served over HTTP, cached for working offline
+    Remote(String),
+    /// A manifest file on this machine
+    Local(PathBuf),
+    /// An offline bundle
+    Bundle(Bundle),
+}
+
+impl Default for ManifestSource {
+    fn default() -> Self {
+        ManifestSource::Remote(super::MANIFEST_URL.to_string())
+    }
+}
+
+/// A manifest loaded from a source
+pub struct LoadedManifest {
+    /// The manifest, if one could be loaded
+    pub manifest: Option<Manifest>,
+    /// Is the source unreachable, so the manifest (if any) came from the cache?
+    pub offline: bool,
+    /// When the manifest was fetched, in seconds since the Unix epoch
+    pub fetched_at: Option<u64>,
+    /// The installer version the manifest needs, if this installer is too old to use it
+    pub required_installer: Option<Version>,
+}
+
+impl From<Manifest> for LoadedManifest {
+    fn from(manifest: Manifest) -> Self {
+        Self {
+            manifest: Some(manifest),
+            offline: false,
+            fetched_at: None,
+            required_installer: None,
+        }
+    }
+}
+
+impl ManifestSource {
+    /// Interpret a manifest source given by the user: an `http(s)://` URL, a
+    /// `file://` URL, or a path. Directories are expected to hold a
+    /// `manifest.json`, laid out like an offline bundle.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            return Ok(ManifestSource::Remote(value.to_string()));
+        }
+        let path = if value.starts_with("file://") {
+            Url::parse(value)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or(format!("{value} is not a valid file URL"))?
+        } else {
+            PathBuf::from(value)
+        };
+        let path = if path.is_dir() {
+            path.join(bundle::BUNDLE_MANIFEST)
+        } else {
+            path
+        };
+        let path = fs::canonicalize(&path)
+            .map_err(|e| format!("Failed to find manifest {}: {e}", path.display()))?;
+        Ok(ManifestSource::Local(path))
+    }
+
+    /// Load the manifest from this source. If `offline` is set, no network
+    /// requests are made and the cached manifest is used instead.
+    pub async fn load(&self, offline: bool) -> Result<LoadedManifest, String> {
+        let loaded = match self {
+            ManifestSource::Remote(url) => {
+                let fetched = if offline {
+                    Ok((ManifestCache::load(url), true))
+                } else {
+                    cache::fetch(url).await
+                };
+                fetched.and_then(|(cache, offline)| {
+                    Ok(LoadedManifest {
+                        fetched_at: cache.as_ref().map(|c| *c.fetched_at()),
+                        manifest: cache.map(|c| c.manifest()).transpose()?,
+                        offline,
+                        required_installer: None,
+                    })
+                })
+            }
+            ManifestSource::Local(path) => read_local(path).map(LoadedManifest::from),
+            ManifestSource::Bundle(bundle) => bundle.manifest().map(LoadedManifest::from),
+        };
+        match loaded {
+            Err(ManifestError::InstallerTooOld(required)) => {
+                tracing::warn!("The manifest needs installer v{required} or newer");
+                Ok(LoadedManifest {
+                    manifest: None,
+                    offline: false,
+                    fetched_at: None,
+                    required_installer: Some(required),
+                })
+            }
+            loaded => loaded.map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Read the manifest as it is, without interpreting it or falling back to
+    /// the cache.
+    pub async fn read_raw(&self) -> Result<Vec<u8>, String> {
+        let path = match self {
+            ManifestSource::Remote(url) => {
+                let res = http::client()
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| format!("Failed to fetch manifest: {e}"))?;
+                let bytes = res
+                    .bytes()
+                    .await
+                    .map_err(|e| format!("Failed to fetch manifest: {e}"))?;
+                return Ok(bytes.to_vec());
+            }
+            ManifestSource::Local(path) => path.clone(),
+            ManifestSource::Bundle(bundle) => bundle.manifest_path(),
+        };
+        fs::read(&path).map_err(|e| format!("Failed to open manifest {}: {e}", path.display()))
+    }
+
+    /// The directory relative download URLs are resolved against, for local sources.
+    pub fn base_dir(&self) -> Option<PathBuf> {
+        match self {
+            ManifestSource::Remote(_) => None,
+            ManifestSource::Local(path) => path.parent().map(Path::to_path_buf),
+            ManifestSource::Bundle(bundle) => {
+                bundle.manifest_path().parent().map(Path::to_path_buf)
+            }
+        }
+    }
+
+    /// Does this source expect the installer to be online, so it should look
+    /// for installer updates?
+    pub fn is_online(&self) -> bool {
+        matches!(self, ManifestSource::Remote(_))
+    }
+
+    /// Describe this source to the user, if it isn't the usual one.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            ManifestSource::Remote(url) if url == super::MANIFEST_URL => None,
+            ManifestSource::Remote(url) => Some(format!("Using the product list from {url}.")),
+            ManifestSource::Local(path) => Some(format!(
+                "Using the product list from {}. Changes to it are shown as soon as they are saved.",
+                path.display()
+            )),
+            ManifestSource::Bundle(bundle) => Some(format!(
+                "Installing from the offline bundle {}.",
+                bundle.path().display()
+            )),
+        }
+    }
+
+    /// Watch a local manifest, calling `on_change` whenever it is changed.
+    /// Returns `None` for sources that can't change under us. The watch stops
+    /// when the returned watcher is dropped.
+    pub fn watch(
+        &self,
+        on_change: impl Fn() + Send + 'static,
+    ) -> Result<Option<Debouncer<RecommendedWatcher>>, String> {
+        let ManifestSource::Local(path) = self else {
+            return Ok(None);
+        };
+        // Editors often save by replacing the file, so watch the directory it is in
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let manifest = path.clone();
+        let mut debouncer =
+            new_debouncer(RELOAD_DELAY, move |res: DebounceEventResult| match res {
+                Ok(events) if events.iter().any(|e| e.path == manifest) => {
+                    tracing::info!("Manifest {manifest:?} changed, reloading");
+                    on_change();
+                }
+                Ok(_) => (),
+                Err(e) => tracing::warn!("Error watching manifest: {e}"),
+            })
+            .map_err(|e| format!("Failed to watch manifest: {e}"))?;
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch manifest: {e}"))?;
+        Ok(Some(debouncer))
+    }
+}
+
+/// Read a manifest from disk, resolving relative URLs against its directory.
+fn read_local(path: &Path) -> Result<Manifest, ManifestError> {
+    let bytes = fs::read(path)
+        .map_err(|e| ManifestError::Invalid(format!("Failed to open manifest: {e}")))?;
+    let mut manifest = Manifest::parse(&bytes).map_err(|e| match e {
+        ManifestError::Invalid(e) => ManifestError::Invalid(format!("{e} ({})", path.display())),
+        e => e,
+    })?;
+    if let Some(dir) = path.parent() {
+        manifest.resolve_relative_urls(dir);
+    }
+    Ok(manifest)
+}
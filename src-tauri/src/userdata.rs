@@ -0,0 +1,203 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Move each of the user data `paths` (relative to `install_directory`) into
+/// `backup_directory`, preserving their relative layout. Paths that don't
+/// exist are skipped.
+pub fn backup(
+    install_directory: &Path,
+    backup_directory: &Path,
+    paths: &[String],
+) -> io::Result<()> {
+    transfer(install_directory, backup_directory, paths)
+}
+
+/// Move each of the `paths` that exist below `from` to the same place below
+/// `to`, replacing anything already there.
+pub fn transfer(from: &Path, to: &Path, paths: &[String]) -> io::Result<()> {
+    for path in paths {
+        let source = from.join(path);
+        if fs::symlink_metadata(&source).is_err() {
+            continue;
+        }
+        let target = to.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove_path(&target)?;
+        tracing::debug!("Moving user data {source:?} to {target:?}");
+        move_path(&source, &target)?;
+    }
+    Ok(())
+}
+
+/// Move each of the user data `paths` from `backup_directory` back into
+/// `install_directory`, replacing anything the install put there. The backup
+/// directory is tidied away once it is empty.
+pub fn restore(
+    backup_directory: &Path,
+    install_directory: &Path,
+    paths: &[String],
+) -> io::Result<()> {
+    if !backup_directory.exists() {
+        return Ok(());
+    }
+    transfer(backup_directory, install_directory, paths)?;
+    if !prune_empty_dirs(backup_directory)? {
+        tracing::warn!(
+            "User data backup {backup_directory:?} still contains data that is no longer listed, leaving it in place"
+        );
+    }
+    Ok(())
+}
+
+/// Remove a file or directory if it exists.
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Move a file or directory, falling back to copy and delete if the target is
+/// on a different volume.
+pub fn move_path(source: &Path, target: &Path) -> io::Result<()> {
+    if fs::rename(source, target).is_ok() {
+        return Ok(());
+    }
+    copy_recursive(source, target)?;
+    remove_path(source)
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(source)?.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(source, target)?;
+    }
+    Ok(())
+}
+
+/// Remove `dir` and any directories below it that contain no files. Returns
+/// whether `dir` itself was removed.
+fn prune_empty_dirs(dir: &Path) -> io::Result<bool> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            empty &= prune_empty_dirs(&entry.path())?;
+        } else {
+            empty = false;
+        }
+    }
+    if empty {
+        fs::remove_dir(dir)?;
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn backup_moves_listed_paths() {
+        let install = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let backup_dir = backup_dir.path().join("p");
+        write(&install.path().join("config/nested/settings.txt"), "mine");
+        write(&install.path().join("saves/one.sav"), "save");
+        write(&install.path().join("app.exe"), "app");
+        let paths = [
+            "config/nested".to_string(),
+            "saves".to_string(),
+            "missing".to_string(),
+        ];
+
+        backup(install.path(), &backup_dir, &paths).unwrap();
+
+        assert_eq!(read(&backup_dir.join("config/nested/settings.txt")), "mine");
+        assert_eq!(read(&backup_dir.join("saves/one.sav")), "save");
+        assert!(!backup_dir.join("missing").exists());
+        assert!(!install.path().join("config/nested").exists());
+        assert!(!install.path().join("saves").exists());
+        assert!(install.path().join("app.exe").exists());
+    }
+
+    #[test]
+    fn transfer_replaces_what_is_there() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        write(&from.path().join("config/settings.txt"), "new");
+        write(&to.path().join("config/settings.txt"), "old");
+        write(&to.path().join("config/stale.txt"), "old");
+
+        transfer(from.path(), to.path(), &["config".to_string()]).unwrap();
+
+        assert_eq!(read(&to.path().join("config/settings.txt")), "new");
+        assert!(!to.path().join("config/stale.txt").exists());
+        assert!(!from.path().join("config").exists());
+    }
+
+    #[test]
+    fn restore_replaces_defaults_and_tidies_the_backup() {
+        let install = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let backup_dir = backups.path().join("p");
+        write(&backup_dir.join("config/nested/settings.txt"), "mine");
+        write(
+            &install.path().join("config/nested/settings.txt"),
+            "default",
+        );
+        write(&install.path().join("config/other.txt"), "default");
+
+        restore(&backup_dir, install.path(), &["config/nested".to_string()]).unwrap();
+
+        let installed = install.path().join("config");
+        assert_eq!(read(&installed.join("nested/settings.txt")), "mine");
+        assert_eq!(read(&installed.join("other.txt")), "default");
+        assert!(!backup_dir.exists());
+    }
+
+    #[test]
+    fn restore_keeps_a_backup_with_unlisted_data() {
+        let install = tempfile::tempdir().unwrap();
+        let backups = tempfile::tempdir().unwrap();
+        let backup_dir = backups.path().join("p");
+        write(&backup_dir.join("config/settings.txt"), "mine");
+        write(&backup_dir.join("old/data.txt"), "kept");
+
+        restore(&backup_dir, install.path(), &["config".to_string()]).unwrap();
+
+        assert_eq!(read(&install.path().join("config/settings.txt")), "mine");
+        assert_eq!(read(&backup_dir.join("old/data.txt")), "kept");
+        assert!(!backup_dir.join("config").exists());
+    }
+
+    #[test]
+    fn restore_without_a_backup_does_nothing() {
+        let install = tempfile::tempdir().unwrap();
+        write(&install.path().join("config/settings.txt"), "default");
+        let missing = install.path().join("no-backup");
+
+        restore(&missing, install.path(), &["config".to_string()]).unwrap();
+
+        assert_eq!(read(&install.path().join("config/settings.txt")), "default");
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use reqwest::header::CONTENT_LENGTH;
+use semver::Version;
+use tauri::Url;
+
+use crate::artifact;
+use crate::http;
+use crate::manifest::{DownloadSpec, DownloadStrategy, Manifest, Platform, ProductVersion};
+
+/// A problem found in a manifest
+pub struct Problem {
+    /// Where in the manifest the problem is
+    location: String,
+    /// What is wrong
+    message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Collects problems as a manifest is checked
+#[derive(Default)]
+pub struct Report {
+    problems: Vec<Problem>,
+}
+
+impl Report {
+    fn problem(&mut self, location: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            location: location.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+}
+
+/// Check the raw manifest JSON against the manifest types. Versions that can't
+/// be read are skipped by the installer rather than failing the whole manifest,
+/// so they are checked one by one here to make sure they are reported.
+pub fn check_syntax(report: &mut Report, bytes: &[u8]) -> Option<Manifest> {
+    let value: serde_json::Value = match serde_json::from_slice(bytes) {
+        Ok(value) => value,
+        Err(e) => {
+            report.problem("manifest", format!("not valid JSON: {e}"));
+            return None;
+        }
+    };
+
+    let products = value.get("products").and_then(|p| p.as_array());
+    for (i, product) in products.into_iter().flatten().enumerate() {
+        let id = product.get("id").and_then(|id| id.as_str()).unwrap_or("?");
+        let versions = product.get("versions").and_then(|v| v.as_array());
+        for (j, version) in versions.into_iter().flatten().enumerate() {
+            if let Err(e) = <ProductVersion as serde::Deserialize>::deserialize(version) {
+                report.problem(format!("products[{i}] ({id}) versions[{j}]"), e.to_string());
+            }
+        }
+    }
+
+    match serde_json::from_value(value) {
+        Ok(manifest) => Some(manifest),
+        Err(e) => {
+            report.problem("manifest", e.to_string());
+            None
+        }
+    }
+}
+
+/// Check a manifest for mistakes that would only show up when installing.
+pub fn check_semantics(report: &mut Report, manifest: &Manifest) {
+    let mut ids = BTreeSet::new();
+    let mut install_directories: HashMap<String, &String> = HashMap::new();
+
+    for prod in manifest.products() {
+        let id = prod.id();
+        if !ids.insert(id) {
+            report.problem(id, "duplicate product ID");
+        }
+
+        if let Err(message) = check_relative_path(prod.install_directory()) {
+            report.problem(id, format!("install_directory {message}"));
+        }
+        // Install directories are compared ignoring case, as they would collide on Windows and macOS
+        let directory = prod
+            .install_directory()
+            .trim_end_matches(['/', '\\'])
+            .to_lowercase();
+        if let Some(other) = install_directories.insert(directory, id) {
+            report.problem(id, format!("install_directory collides with {other}"));
+        }
+
+        for path in prod.user_data() {
+            if let Err(message) = check_relative_path(path) {
+                report.problem(id, format!("user_data {path:?} {message}"));
+            }
+        }
+
+        for (i, removal) in prod.removals().iter().enumerate() {
+            let location = format!("{id} removals[{i}]");
+            for platform in removal.on().iter().flatten() {
+                if let Err(e) = platform.parse::<Platform>() {
+                    report.problem(&location, e);
+                }
+            }
+            for path in removal.files() {
+                if let Err(message) = check_relative_path(path) {
+                    report.problem(&location, format!("{path:?} {message}"));
+                }
+            }
+        }
+
+        if let Some(probe) = prod.version_probe() {
+            if let Err(message) = check_relative_path(probe.file()) {
+                report.problem(id, format!("version_probe file {message}"));
+            }
+            if let Some(pattern) = probe.pattern() {
+                if let Err(e) = regex::Regex::new(pattern) {
+                    report.problem(id, format!("version_probe pattern is invalid: {e}"));
+                }
+            }
+        }
+
+        if prod.versions().is_empty() {
+            report.problem(id, "no versions");
+        }
+        let mut versions = BTreeSet::new();
+        for version in prod.versions() {
+            if !versions.insert(version.version()) {
+                report.problem(id, format!("duplicate version {}", version.version()));
+            }
+        }
+
+        // Every platform offered by an earlier version should still be offered by the latest
+        let offered: BTreeSet<_> = prod
+            .versions()
+            .iter()
+            .flat_map(|v| {
+                Platform::ALL
+                    .into_iter()
+                    .filter(|&p| v.downloads().for_platform(p).is_some())
+            })
+            .collect();
+        // Channels often share their latest version, which only needs checking once
+        let latest_versions: BTreeMap<_, _> = manifest
+            .available_channels()
+            .iter()
+            .filter_map(|channel| prod.latest_version(channel, None))
+            .map(|version| (version.version(), version))
+            .collect();
+        for (latest, version) in latest_versions {
+            for &platform in &offered {
+                if version.downloads().for_platform(platform).is_none() {
+                    report.problem(
+                        format!("{id} v{latest}"),
+                        format!("latest version has no {platform} download"),
+                    );
+                }
+            }
+        }
+
+        for version in prod.versions() {
+            if let Some(rollout) = version.rollout() {
+                let location = format!("{id} v{} rollout", version.version());
+                if *rollout.percentage() > 100 {
+                    report.problem(&location, "percentage is over 100");
+                }
+                if let Some(start) = rollout.start() {
+                    if let Err(e) = humantime::parse_rfc3339_weak(start) {
+                        report.problem(
+                            &location,
+                            format!("start {start:?} is not a date and time: {e}"),
+                        );
+                    }
+                }
+            }
+            for platform in Platform::ALL {
+                if let Some(download) = version.downloads().for_platform(platform) {
+                    let location = format!("{id} v{} {platform}", version.version());
+                    check_download(report, &location, &download);
+                }
+            }
+        }
+    }
+}
+
+fn check_download(report: &mut Report, location: &str, download: &DownloadSpec) {
+    for source in download.sources() {
+        if Url::parse(source).is_err() && check_relative_path(source).is_err() {
+            report.problem(location, format!("{source:?} is not a URL"));
+        }
+    }
+    if let Some(sha256) = download.sha256() {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            report.problem(location, "sha256 is not a hex encoded SHA-256 hash");
+        }
+    }
+    if let Some(executable) = download.executable() {
+        if let Err(message) = check_relative_path(executable) {
+            report.problem(location, format!("executable {message}"));
+        }
+        if download.executable_absolute().is_some() {
+            report.problem(location, "both executable and executable_absolute are set");
+        }
+        if let DownloadStrategy::File { name, .. } = download.strategy() {
+            if name != executable {
+                report.problem(
+                    location,
+                    format!("executable {executable:?} isn't the downloaded file {name:?}"),
+                );
+            }
+        }
+    }
+}
+
+/// Check every download URL is absolute, as relative URLs can't be followed
+/// from a manifest that is read from a URL.
+pub fn check_absolute_urls(report: &mut Report, manifest: &Manifest) {
+    for prod in manifest.products() {
+        for version in prod.versions() {
+            for platform in Platform::ALL {
+                let Some(download) = version.downloads().for_platform(platform) else {
+                    continue;
+                };
+                let location = format!("{} v{} {platform}", prod.id(), version.version());
+                for source in download.sources() {
+                    if Url::parse(source).is_err() {
+                        report.problem(
+                            &location,
+                            format!("{source:?} is relative, which only works for manifests read from a file or bundle"),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Check a path is relative and stays inside the directory it is relative to.
+fn check_relative_path(path: &str) -> Result<(), &'static str> {
+    let p = Path::new(path);
+    if path.is_empty() {
+        Err("is empty")
+    } else if p.has_root() || path.contains(':') {
+        Err("must be a relative path")
+    } else if path.split(['/', '\\']).any(|part| part == "..") {
+        Err("must not contain ..")
+    } else {
+        Ok(())
+    }
+}
+
+/// Check every download URL resolves. If `download` is set, artifacts are also
+/// downloaded to check their size and checksum and that they contain their
+/// executable. Only the latest versions are downloaded unless `all_versions`.
+pub async fn check_downloads(
+    report: &mut Report,
+    manifest: &Manifest,
+    download: bool,
+    all_versions: bool,
+) {
+    for prod in manifest.products() {
+        let latest: Vec<Version> = manifest
+            .available_channels()
+            .iter()
+            .filter_map(|channel| prod.latest_version(channel, None))
+            .map(|v| v.version().clone())
+            .collect();
+        for version in prod.versions() {
+            for platform in Platform::ALL {
+                let Some(spec) = version.downloads().for_platform(platform) else {
+                    continue;
+                };
+                let location = format!("{} v{} {platform}", prod.id(), version.version());
+                // Relative URLs are reported by the other checks rather than followed
+                for source in spec.sources().filter(|s| Url::parse(s).is_ok()) {
+                    if let Err(e) = check_url(source, *spec.size()).await {
+                        report.problem(&location, format!("{source}: {e}"));
+                    }
+                }
+                if download && (all_versions || latest.contains(version.version())) {
+                    tracing::info!("Downloading {location}");
+                    if let Err(e) = check_artifact(&spec).await {
+                        report.problem(&location, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Check a URL can be downloaded from, and has the expected size if known.
+async fn check_url(url: &str, size: Option<u64>) -> Result<(), String> {
+    let actual = if let Some(path) = Url::parse(url)
+        .ok()
+        .filter(|u| u.scheme() == "file")
+        .and_then(|u| u.to_file_path().ok())
+    {
+        Some(
+            std::fs::metadata(path)
+                .map_err(|e| format!("can't be read: {e}"))?
+                .len(),
+        )
+    } else {
+        let res = http::client()
+            .head(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("can't be reached: {e}"))?;
+        res.headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    };
+    match (size, actual) {
+        (Some(size), Some(actual)) if size != actual => {
+            Err(format!("is {actual} bytes, but the manifest says {size}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Download an artifact, checking it against its size and checksum, and make
+/// sure it contains its executable.
+async fn check_artifact(spec: &DownloadSpec) -> Result<(), String> {
+    let dir =
+        tempfile::tempdir().map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+    let downloaded = artifact::download(spec, dir.path()).await?;
+    if matches!(spec.strategy(), DownloadStrategy::Msi { .. }) {
+        return Ok(());
+    }
+    let staging = dir.path().join("staging");
+    artifact::stage(spec.strategy(), &downloaded.path, &staging)?;
+    if let Some(executable) = spec.executable() {
+        if !staging.join(executable).is_file() {
+            return Err(format!("executable {executable:?} isn't in the download"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn missing_downloads_are_reported_once() {
+        let download = json!({ "url": "https://example.com/p.zip", "strategy": "ZipFile" });
+        let manifest = json!({
+            "products": [{
+                "id": "p",
+                "name": "P",
+                "description": "",
+                "install_directory": "p",
+                "removals": [],
+                "versions": [
+                    { "version": "1.0.0", "downloads": { "windows": download, "linux": download } },
+                    { "version": "1.1.0", "downloads": { "windows": download } },
+                ],
+            }],
+        });
+        let manifest = Manifest::parse(manifest.to_string().as_bytes()).unwrap();
+        let mut report = Report::default();
+
+        check_semantics(&mut report, &manifest);
+
+        let problems: Vec<_> = report.problems().iter().map(ToString::to_string).collect();
+        assert_eq!(problems, ["p v1.1.0: latest version has no linux download"]);
+    }
+}
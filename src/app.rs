@@ -1,3 +1,4 @@
+use pulldown_cmark::Parser;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
@@ -9,11 +10,20 @@ extern "C" {
     #[wasm_bindgen(catch)]
     async fn invoke(cmd: &str, args: JsValue) -> Result<JsValue, JsValue>;
 
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "event"])]
+    async fn listen(event: &str, handler: &Closure<dyn Fn(JsValue)>) -> JsValue;
+
     #[wasm_bindgen(js_namespace = ["window", "__TAURI_PLUGIN_DIALOG__"])]
     fn dialog(s: &str, opts: JsValue);
 
     #[wasm_bindgen(js_namespace = ["window", "__TAURI_PLUGIN_DIALOG__"])]
     async fn confirm(s: &str, opts: JsValue) -> JsValue;
+
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI_PLUGIN_DIALOG__"])]
+    async fn open(opts: JsValue) -> JsValue;
+
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI_PLUGIN_DIALOG__"])]
+    async fn save(opts: JsValue) -> JsValue;
 }
 
 #[derive(Serialize)]
@@ -22,13 +32,146 @@ struct DialogOptions<'a> {
     kind: &'a str,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfirmOptions<'a> {
+    title: &'a str,
+    kind: &'a str,
+    ok_label: &'a str,
+    cancel_label: &'a str,
+}
+
+#[derive(Serialize)]
+struct OpenOptions<'a> {
+    title: &'a str,
+    directory: bool,
+}
+
+/// Ask the user to pick a directory, returning `None` if they cancel.
+async fn pick_directory(title: &str) -> Option<String> {
+    open(
+        serde_wasm_bindgen::to_value(&OpenOptions {
+            title,
+            directory: true,
+        })
+        .unwrap(),
+    )
+    .await
+    .as_string()
+}
+
+#[derive(Serialize)]
+struct FileOptions<'a> {
+    title: &'a str,
+    filters: [FileFilter<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct FileFilter<'a> {
+    name: &'a str,
+    extensions: [&'a str; 1],
+}
+
+/// The file options for picking a profile
+fn profile_file_options(title: &str) -> JsValue {
+    serde_wasm_bindgen::to_value(&FileOptions {
+        title,
+        filters: [FileFilter {
+            name: "AngelSuite Profile",
+            extensions: ["json"],
+        }],
+    })
+    .unwrap()
+}
+
+/// Something to do to make this machine match a profile
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ProfileAction {
+    Install {
+        id: String,
+        name: String,
+        version: String,
+    },
+    Update {
+        id: String,
+        name: String,
+        from: String,
+        to: String,
+    },
+    Downgrade {
+        id: String,
+        name: String,
+        from: String,
+        to: String,
+    },
+    Remove {
+        id: String,
+        name: String,
+        version: String,
+    },
+}
+
+/// What it would take to make this machine match a profile
+#[derive(Clone, Deserialize, PartialEq)]
+struct ProfilePlan {
+    actions: Vec<ProfileAction>,
+    /// Parts of the profile that can't be matched
+    problems: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportProfileArgs {
+    path: String,
+    exact: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileArgs {
+    path: String,
+}
+
 #[derive(Deserialize, Default)]
 struct ManifestLoadResult {
     can_auto_update: bool,
     installer_update_available: Option<String>,
+    /// Is the manifest unavailable, so we are working from the cache or installed products?
+    offline: bool,
+    /// When the manifest in use was last fetched, in seconds since the Unix epoch
+    manifest_fetched_at: Option<u64>,
+    /// Where the manifest came from, if it isn't the usual place
+    manifest_source: Option<String>,
+    /// The installer version the manifest needs, if this installer is too old to use it
+    required_installer: Option<String>,
+    /// The release channels products can follow, most stable first
+    channels: Vec<Channel>,
+    /// The directory new products are installed under
+    install_root: String,
+    /// Does this machine get new versions before their rollout reaches it?
+    early_access: bool,
     products: Vec<ManifestLoadResultProduct>,
 }
 
+/// A copy of a product in its install directory that isn't recorded as installed
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct Existing {
+    /// The directory the copy is in
+    pub directory: String,
+    /// The version of the copy, if it could be identified
+    pub version: Option<String>,
+}
+
+/// A release channel a product can follow
+#[derive(Clone, Deserialize, PartialEq)]
+pub struct Channel {
+    /// The ID of the channel
+    pub id: String,
+    /// The name of the channel to show to users
+    pub name: String,
+}
+
 #[derive(Clone, Properties, Deserialize, PartialEq)]
 pub struct ManifestLoadResultProduct {
     /// The internal ID of this product
@@ -39,32 +182,73 @@ pub struct ManifestLoadResultProduct {
     pub icon: Option<String>,
     /// The local installed version of this product, if installed
     pub local_version: Option<String>,
-    /// The latest remote version of this product, excluding prereleases
-    pub remote_version: String,
-    /// The latest remote version of this product, including prereleases
-    pub remote_version_prerelease: String,
+    /// The version of this product in its channel to install on this system, if any has a build for it
+    pub remote_version: Option<String>,
+    /// The latest version of this product in its channel that has reached this machine, even if it has no build for this system
+    pub latest_version: Option<String>,
+    /// Is the remote version newer than the installed version?
+    pub update_available: bool,
+    /// Is the remote version older than the installed version, which should be moved off as it was withdrawn or is outside its pin?
+    pub downgrade_needed: bool,
+    /// The closest version to move to, if the installed version has been withdrawn
+    pub recommended_version: Option<String>,
     /// The description of this product
     pub description: String,
-    /// Is there a package available that matches this OS, excluding prereleases?
-    pub has_os_match_prerelease: bool,
-    /// Is there a package available that matches this OS, including prereleases?
-    pub has_os_match: bool,
     /// Can this installation be started?
     pub can_start: bool,
-    /// Prerelease enabled
-    pub allow_prerelease: bool,
+    /// The ID of the release channel this product follows, if known
+    pub channel: Option<String>,
+    /// The range of versions this product is held within, if it is pinned
+    pub pin: Option<String>,
+    /// The newest version in its channel the pin is holding this product back from
+    pub held_version: Option<String>,
+    /// Does this product keep user data that can survive removal?
+    pub has_user_data: bool,
+    /// The size of the installation on disk in bytes, if installed
+    pub installed_size: Option<u64>,
+    /// Has the installed version been withdrawn?
+    pub local_yanked: bool,
+    /// Why the installed version was withdrawn, if it has been
+    pub yank_reason: Option<String>,
+    /// The installer version needed for the newest version in its channel, if this installer is too old
+    pub required_installer: Option<String>,
+    /// The directory this product is installed in, or will be installed in
+    pub install_directory: Option<String>,
+    /// A copy of this product found where it would be installed, if it isn't recorded as installed
+    pub existing: Option<Existing>,
 }
 
 #[function_component(App)]
 pub fn app() -> Html {
     let progress_message = use_state(|| None::<String>);
     let update_manifest = use_state(|| 0);
+    let manifest_revision = use_state(|| 0);
     let manifest_load_result = use_state(ManifestLoadResult::default);
+    let pending_profile = use_state(|| None::<(String, ProfilePlan)>);
+
+    {
+        // Local manifests are watched, and a new revision is announced whenever they change
+        let manifest_revision = manifest_revision.clone();
+        use_effect_with((), |_| {
+            spawn_local(async move {
+                let handler = Closure::<dyn Fn(JsValue)>::new(move |event: JsValue| {
+                    if let Some(revision) = js_sys::Reflect::get(&event, &"payload".into())
+                        .ok()
+                        .and_then(|p| p.as_f64())
+                    {
+                        manifest_revision.set(revision as u32);
+                    }
+                });
+                listen("manifest-changed", &handler).await;
+                handler.forget();
+            });
+        });
+    }
 
     {
         let manifest_load_result = manifest_load_result.clone();
-        let update_manifest = update_manifest.clone();
-        use_effect_with(update_manifest, |_| {
+        let deps = (*update_manifest, *manifest_revision);
+        use_effect_with(deps, |_| {
             spawn_local(async move {
                 match invoke("load_manifest", JsValue::null()).await {
                     Ok(res) => {
@@ -98,6 +282,199 @@ pub fn app() -> Html {
         })
     };
 
+    let onclick_export_profile = Callback::from(move |e: MouseEvent| {
+        e.prevent_default();
+        spawn_local(async move {
+            let Some(path) = save(profile_file_options("Export profile"))
+                .await
+                .as_string()
+            else {
+                return;
+            };
+            let exact = confirm(
+                "Should everyone using this profile get the same versions you have, or the latest versions?",
+                serde_wasm_bindgen::to_value(&ConfirmOptions {
+                    title: "Export profile",
+                    kind: "info",
+                    ok_label: "Same Versions",
+                    cancel_label: "Latest Versions",
+                })
+                .unwrap(),
+            )
+            .await;
+            let args = serde_wasm_bindgen::to_value(&ExportProfileArgs {
+                path,
+                // SAFETY: confirm always returns bool
+                exact: exact.as_bool().unwrap(),
+            })
+            .unwrap();
+            if let Err(e) = invoke("export_profile", args).await {
+                dialog(
+                    &e.as_string().unwrap(),
+                    serde_wasm_bindgen::to_value(&DialogOptions {
+                        title: "Couldn't export profile",
+                        kind: "error",
+                    })
+                    .unwrap(),
+                );
+            }
+        });
+    });
+
+    let onclick_import_profile = {
+        let pending_profile = pending_profile.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            let pending_profile = pending_profile.clone();
+            spawn_local(async move {
+                let Some(path) = open(profile_file_options("Import profile"))
+                    .await
+                    .as_string()
+                else {
+                    return;
+                };
+                let args =
+                    serde_wasm_bindgen::to_value(&ProfileArgs { path: path.clone() }).unwrap();
+                match invoke("preview_profile", args).await {
+                    Ok(plan) => pending_profile
+                        .set(Some((path, serde_wasm_bindgen::from_value(plan).unwrap()))),
+                    Err(e) => dialog(
+                        &e.as_string().unwrap(),
+                        serde_wasm_bindgen::to_value(&DialogOptions {
+                            title: "Couldn't import profile",
+                            kind: "error",
+                        })
+                        .unwrap(),
+                    ),
+                }
+            });
+        })
+    };
+
+    let profile_modal = pending_profile.as_ref().map(|(path, plan)| {
+        let onclick_cancel = {
+            let pending_profile = pending_profile.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.prevent_default();
+                pending_profile.set(None);
+            })
+        };
+        let onclick_confirm = {
+            let path = path.clone();
+            let pending_profile = pending_profile.clone();
+            let cb = cb_set_progress_message.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.prevent_default();
+                pending_profile.set(None);
+
+                let path = path.clone();
+                let cb = cb.clone();
+                spawn_local(async move {
+                    cb.emit((Some("Importing profile...".to_string()), false));
+                    let args = serde_wasm_bindgen::to_value(&ProfileArgs { path }).unwrap();
+                    let plan: ProfilePlan = match invoke("import_profile", args).await {
+                        Ok(plan) => serde_wasm_bindgen::from_value(plan).unwrap(),
+                        Err(e) => {
+                            cb.emit((None, true));
+                            dialog(
+                                &e.as_string().unwrap(),
+                                serde_wasm_bindgen::to_value(&DialogOptions {
+                                    title: "Couldn't import profile",
+                                    kind: "error",
+                                })
+                                .unwrap(),
+                            );
+                            return;
+                        }
+                    };
+
+                    let mut failures = vec![];
+                    for action in plan.actions {
+                        let (name, result) = match action {
+                            ProfileAction::Install { id, name, .. }
+                            | ProfileAction::Update { id, name, .. }
+                            | ProfileAction::Downgrade { id, name, .. } => {
+                                cb.emit((Some(format!("Installing {name}...")), false));
+                                let args =
+                                    serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                                        id,
+                                    })
+                                    .unwrap();
+                                (name, invoke("install_app", args).await)
+                            }
+                            ProfileAction::Remove { id, name, .. } => {
+                                cb.emit((Some(format!("Removing {name}...")), false));
+                                let args = serde_wasm_bindgen::to_value(&RemoveArgs {
+                                    id,
+                                    remove_user_data: false,
+                                })
+                                .unwrap();
+                                (name, invoke("remove_app", args).await)
+                            }
+                        };
+                        if let Err(e) = result {
+                            failures.push(format!("{name}: {}", e.as_string().unwrap()));
+                        }
+                    }
+                    cb.emit((None, true));
+                    if !failures.is_empty() {
+                        dialog(
+                            &failures.join("\n"),
+                            serde_wasm_bindgen::to_value(&DialogOptions {
+                                title: "Some products couldn't be changed",
+                                kind: "warning",
+                            })
+                            .unwrap(),
+                        );
+                    }
+                });
+            })
+        };
+        let actions: Vec<_> = plan
+            .actions
+            .iter()
+            .map(|action| {
+                let text = match action {
+                    ProfileAction::Install { name, version, .. } => {
+                        format!("Install {name} v{version}")
+                    }
+                    ProfileAction::Update { name, from, to, .. } => {
+                        format!("Update {name} from v{from} to v{to}")
+                    }
+                    ProfileAction::Downgrade { name, from, to, .. } => {
+                        format!("Downgrade {name} from v{from} to v{to}")
+                    }
+                    ProfileAction::Remove { name, version, .. } => {
+                        format!("Remove {name} v{version}, keeping its data")
+                    }
+                };
+                html! { <li>{ text }</li> }
+            })
+            .collect();
+        let problems: Vec<_> = plan
+            .problems
+            .iter()
+            .map(|problem| html! { <li class="profile__problem">{ problem }</li> })
+            .collect();
+        html! {
+            <div class="modal">
+                <div class="modal__content">
+                    <h2>{ "Import profile" }</h2>
+                    if plan.actions.is_empty() {
+                        <p>{ "Everything already matches this profile." }</p>
+                    } else {
+                        <ul class="profile__actions">{ actions }</ul>
+                    }
+                    <ul>{ problems }</ul>
+                    <div class="modal__buttons">
+                        <button class="btn" onclick={ onclick_cancel }>{ "Cancel" }</button>
+                        <button class="btn" onclick={ onclick_confirm }>{ "Import" }</button>
+                    </div>
+                </div>
+            </div>
+        }
+    });
+
     let onclick_update = {
         let cb = cb_set_progress_message.clone();
         Callback::from(move |e: MouseEvent| {
@@ -133,6 +510,147 @@ pub fn app() -> Html {
             }
         });
 
+    let offline_notification = manifest_load_result.offline.then(|| {
+        let message = match manifest_load_result.manifest_fetched_at {
+            Some(fetched_at) => {
+                let now = (js_sys::Date::now() / 1000.0) as u64;
+                format!(
+                    "You are working offline. The product list was last updated {} ago.",
+                    format_age(now.saturating_sub(fetched_at))
+                )
+            }
+            None => "You are working offline. Only installed products are shown.".to_string(),
+        };
+        html! {
+            <p class="offline-notification">{ message }</p>
+        }
+    });
+
+    let required_installer_notification = manifest_load_result.required_installer.clone().map(|v| {
+        let message = if manifest_load_result.installer_update_available.is_some() {
+            format!("The product list needs installer v{v} or newer. Update the installer above to see and install products.")
+        } else {
+            format!("The product list needs installer v{v} or newer. Download the latest installer to see and install products.")
+        };
+        html! {
+            <p class="update-notification">{ message }</p>
+        }
+    });
+
+    let source_notification = manifest_load_result.manifest_source.clone().map(|source| {
+        html! {
+            <p class="source-notification">{ source }</p>
+        }
+    });
+
+    let install_root = (!manifest_load_result.install_root.is_empty()).then(|| {
+        let cb = cb_set_progress_message.clone();
+        let onclick_change = Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            let cb = cb.clone();
+            spawn_local(async move {
+                let Some(root) = pick_directory("Install new products in").await else {
+                    return;
+                };
+                let args =
+                    serde_wasm_bindgen::to_value(&SetInstallRootArgs { root: Some(root) }).unwrap();
+                if let Err(e) = invoke("set_install_root", args).await {
+                    dialog(
+                        &e.as_string().unwrap(),
+                        serde_wasm_bindgen::to_value(&DialogOptions {
+                            title: "Couldn't change install location",
+                            kind: "error",
+                        })
+                        .unwrap(),
+                    );
+                }
+                cb.emit((None, true));
+            });
+        });
+        html! {
+            <p class="install-root">
+                { "New products are installed in " }{ &manifest_load_result.install_root }
+                <button class="btn" onclick={ onclick_change }>{ "Change" }</button>
+            </p>
+        }
+    });
+
+    let early_access = (!manifest_load_result.install_root.is_empty()).then(|| {
+        let cb = cb_set_progress_message.clone();
+        let onchange_early_access = Callback::from(move |e: Event| {
+            let enabled = e
+                .target_unchecked_into::<web_sys::HtmlInputElement>()
+                .checked();
+            let cb = cb.clone();
+            spawn_local(async move {
+                let args = serde_wasm_bindgen::to_value(&SetEarlyAccessArgs { enabled }).unwrap();
+                if let Err(e) = invoke("set_early_access", args).await {
+                    dialog(
+                        &e.as_string().unwrap(),
+                        serde_wasm_bindgen::to_value(&DialogOptions {
+                            title: "Couldn't change early access",
+                            kind: "error",
+                        })
+                        .unwrap(),
+                    );
+                }
+                cb.emit((None, true));
+            });
+        });
+        html! {
+            <p class="early-access">
+                <label>
+                    <input type="checkbox" checked={ manifest_load_result.early_access } onchange={ onchange_early_access } />
+                    { " Get new versions before they reach everyone" }
+                </label>
+            </p>
+        }
+    });
+
+    // Products with an update available, within their pins. Downgrades are
+    // left for the user to choose one at a time.
+    let updatable: Vec<_> = manifest_load_result
+        .products
+        .iter()
+        .filter(|prod| prod.update_available)
+        .map(|prod| (prod.id.clone(), prod.name.clone()))
+        .collect();
+
+    let update_all_button = (!updatable.is_empty()).then(|| {
+        let cb = cb_set_progress_message.clone();
+        let onclick_update_all = Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+
+            let updatable = updatable.clone();
+            let cb = cb.clone();
+            spawn_local(async move {
+                let mut failures = vec![];
+                for (id, name) in updatable {
+                    cb.emit((Some(format!("Updating {name}...")), false));
+                    let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs { id })
+                        .unwrap();
+                    if let Err(e) = invoke("install_app", args).await {
+                        failures.push(format!("{name}: {}", e.as_string().unwrap()));
+                    }
+                }
+                cb.emit((None, true));
+                if !failures.is_empty() {
+                    dialog(
+                        &failures.join("\n"),
+                        serde_wasm_bindgen::to_value(&DialogOptions {
+                            title: "Some updates failed",
+                            kind: "warning",
+                        })
+                        .unwrap(),
+                    );
+                }
+            });
+        });
+        html! {
+            <p><button class="btn" onclick={ onclick_update_all }>{ "Update All" }</button></p>
+        }
+    });
+
     let items: Vec<_> = manifest_load_result
         .products
         .iter()
@@ -145,12 +663,23 @@ pub fn app() -> Html {
                     icon={ prod.icon }
                     local_version={ prod.local_version }
                     remote_version={ prod.remote_version }
-                    remote_version_prerelease={ prod.remote_version_prerelease }
+                    update_available={ prod.update_available }
+                    downgrade_needed={ prod.downgrade_needed }
+                    recommended_version={ prod.recommended_version }
+                    latest_version={ prod.latest_version }
                     description={ prod.description }
-                    allow_prerelease={ prod.allow_prerelease }
-                    has_os_match_prerelease={ prod.has_os_match_prerelease }
-                    has_os_match={ prod.has_os_match }
+                    channel={ prod.channel }
+                    channels={ manifest_load_result.channels.clone() }
+                    pin={ prod.pin }
+                    held_version={ prod.held_version }
                     can_start={ prod.can_start }
+                    has_user_data={ prod.has_user_data }
+                    installed_size={ prod.installed_size }
+                    local_yanked={ prod.local_yanked }
+                    yank_reason={ prod.yank_reason }
+                    required_installer={ prod.required_installer }
+                    install_directory={ prod.install_directory }
+                    existing={ prod.existing }
                     set_progress_message={ &cb_set_progress_message } />
             }
         })
@@ -162,12 +691,18 @@ pub fn app() -> Html {
                 <img src="/public/icon.png" aria-hidden="true" alt="" />
                 <h1>{"AngelSuite"}</h1>
             </div>
-            <div style={ if progress_message.is_some() { "display:none" } else { "" } }>{ update_notification }</div>
+            <div style={ if progress_message.is_some() { "display:none" } else { "" } }>{ update_notification }{ required_installer_notification }{ source_notification }{ offline_notification }{ install_root }{ early_access }{ update_all_button }
+                <p class="profile">
+                    <button class="btn" onclick={ onclick_export_profile }>{ "Export Profile" }</button>
+                    <button class="btn" onclick={ onclick_import_profile }>{ "Import Profile" }</button>
+                </p>
+            </div>
             <p hidden={ progress_message.is_none() }>{ &*progress_message }</p>
 
             <div class="scrolling-list" style={ if progress_message.is_some() { "display:none" } else { "" } }>
                 { items }
             </div>
+            { profile_modal }
         </>
     }
 }
@@ -182,20 +717,42 @@ pub struct ItemProps {
     pub icon: Option<String>,
     /// The local installed version of this product, if installed
     pub local_version: Option<String>,
-    /// The latest remote version of this product, excluding prereleases
-    pub remote_version: String,
-    /// The latest remote version of this product, including prereleases
-    pub remote_version_prerelease: String,
+    /// The version of this product in its channel to install on this system, if any has a build for it
+    pub remote_version: Option<String>,
+    /// The latest version of this product in its channel that has reached this machine, even if it has no build for this system
+    pub latest_version: Option<String>,
+    /// Is the remote version newer than the installed version?
+    pub update_available: bool,
+    /// Is the remote version older than the installed version, which should be moved off as it was withdrawn or is outside its pin?
+    pub downgrade_needed: bool,
+    /// The closest version to move to, if the installed version has been withdrawn
+    pub recommended_version: Option<String>,
     /// The description of this product
     pub description: String,
-    /// Prerelease enabled
-    pub allow_prerelease: bool,
-    /// Is there a package available that matches this OS, excluding prereleases?
-    pub has_os_match_prerelease: bool,
-    /// Is there a package available that matches this OS, including prereleases?
-    pub has_os_match: bool,
+    /// The ID of the release channel this product follows, if known
+    pub channel: Option<String>,
+    /// The release channels the product can follow
+    pub channels: Vec<Channel>,
+    /// The range of versions this product is held within, if it is pinned
+    pub pin: Option<String>,
+    /// The newest version in its channel the pin is holding this product back from
+    pub held_version: Option<String>,
     /// Can this installation be started?
     pub can_start: bool,
+    /// Does this product keep user data that can survive removal?
+    pub has_user_data: bool,
+    /// The size of the installation on disk in bytes, if installed
+    pub installed_size: Option<u64>,
+    /// Has the installed version been withdrawn?
+    pub local_yanked: bool,
+    /// Why the installed version was withdrawn, if it has been
+    pub yank_reason: Option<String>,
+    /// The installer version needed for the newest version in its channel, if this installer is too old
+    pub required_installer: Option<String>,
+    /// The directory this product is installed in, or will be installed in
+    pub install_directory: Option<String>,
+    /// A copy of this product found where it would be installed, if it isn't recorded as installed
+    pub existing: Option<Existing>,
     /// Update the progress message
     pub set_progress_message: Callback<(Option<String>, bool)>,
 }
@@ -203,14 +760,41 @@ pub struct ItemProps {
 enum State {
     InstalledLatest(String),
     InstalledUpdate(String, String),
-    NotInstalled(String),
+    InstalledDowngrade(String, String),
+    NotInstalled(Option<String>),
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct SetPrereleaseArgs {
+struct SetChannelArgs {
     id: String,
-    allow_prerelease: bool,
+    channel: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetEarlyAccessArgs {
+    enabled: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetInstallRootArgs {
+    root: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MoveArgs {
+    id: String,
+    root: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetPinArgs {
+    id: String,
+    pin: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -219,48 +803,113 @@ struct StartInstallUpgradeRemoveArgs {
     id: String,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstallArgs {
+    id: String,
+    /// The version to install, rather than the one offered
+    version: Option<String>,
+}
+
+/// The result of checking an installation against its record of installed files
+#[derive(Deserialize)]
+struct Verification {
+    /// Installed files that no longer exist
+    missing: Vec<String>,
+    /// Installed files that have changed since they were installed
+    modified: Vec<String>,
+    /// Files in the install directory that weren't installed by us
+    extra: Vec<String>,
+}
+
+/// What changed in a version of a product
+#[derive(Clone, Deserialize, PartialEq)]
+struct ReleaseNotes {
+    /// The version these notes are for
+    version: String,
+    /// When the version was released, as an ISO 8601 date
+    released_at: Option<String>,
+    /// The notes as Markdown, if they are in the manifest
+    notes: Option<String>,
+    /// A link to the notes, if they are published elsewhere
+    url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoveArgs {
+    id: String,
+    remove_user_data: bool,
+}
+
 #[function_component(Item)]
 pub fn item(props: &ItemProps) -> Html {
     let id = use_state(|| props.id.clone());
-    let allow_prereleases = use_state(|| props.allow_prerelease);
     let install_error = use_state(String::new);
+    let pending_release_notes = use_state(|| None::<Vec<ReleaseNotes>>);
 
-    let remote_version = if *allow_prereleases {
-        &props.remote_version_prerelease
-    } else {
-        &props.remote_version
-    };
-    let has_os_match = if *allow_prereleases {
-        props.has_os_match_prerelease
-    } else {
-        props.has_os_match
-    };
-    let state = if let Some(local_version) = props.local_version.as_ref() {
-        if local_version == remote_version || local_version != "0.0.0" && remote_version == "0.0.0"
-        {
-            State::InstalledLatest(local_version.clone())
-        } else {
+    let state = match (&props.local_version, &props.remote_version) {
+        (Some(local_version), Some(remote_version)) if props.update_available => {
             State::InstalledUpdate(local_version.clone(), remote_version.clone())
         }
-    } else {
-        State::NotInstalled(remote_version.clone())
+        (Some(local_version), Some(remote_version)) if props.downgrade_needed => {
+            State::InstalledDowngrade(local_version.clone(), remote_version.clone())
+        }
+        (Some(local_version), _) => State::InstalledLatest(local_version.clone()),
+        (None, remote_version) => State::NotInstalled(remote_version.clone()),
     };
 
     let state_str = match &state {
+        // Newer than anything offered, such as after moving to a more stable channel
+        State::InstalledLatest(v) if props.remote_version.as_ref().is_some_and(|r| r != v) => {
+            format!("Installed v{v}")
+        }
         State::InstalledLatest(v) => format!("Installed v{v} (latest)"),
         State::InstalledUpdate(v, l) => format!("Installed v{v} (updatable to v{l})"),
-        State::NotInstalled(l) => {
-            if l == "0.0.0" || !has_os_match {
-                "Not available for your system".to_string()
-            } else {
-                format!("v{} available", l)
-            }
+        State::InstalledDowngrade(v, l) if props.local_yanked => {
+            format!("Installed v{v} (withdrawn, v{l} available)")
+        }
+        State::InstalledDowngrade(v, l) => {
+            format!("Installed v{v} (outside its pin, can go back to v{l})")
+        }
+        State::NotInstalled(Some(l)) => format!("v{l} available"),
+        State::NotInstalled(None) => "Not available for your system".to_string(),
+    };
+
+    // Say when the newest version is missing a build for this system, so an older one is offered
+    let state_str = match (&props.remote_version, &props.latest_version) {
+        (Some(remote), Some(latest)) if remote != latest => {
+            format!("{state_str}, v{latest} isn't available for your system yet")
+        }
+        _ => state_str,
+    };
+
+    let state_str = match (&state, &props.required_installer) {
+        (State::NotInstalled(None), Some(required)) => {
+            format!("Needs installer v{required} or newer")
+        }
+        (_, Some(required)) => {
+            format!("{state_str}, a newer version needs installer v{required}")
         }
+        _ => state_str,
+    };
+
+    let state_str = match (&state, &props.pin, &props.held_version) {
+        (State::NotInstalled(_), _, _) => state_str,
+        (_, Some(pin), Some(held)) => format!("{state_str}, held at {pin} (v{held} available)"),
+        (_, Some(pin), None) => format!("{state_str}, held at {pin}"),
+        _ => state_str,
+    };
+
+    let state_str = match (&state, props.installed_size) {
+        (State::NotInstalled(_), _) | (_, None) => state_str,
+        (_, Some(size)) => format!("{state_str}, {} on disk", format_size(size)),
     };
 
     let hide_install_upgrade = match &state {
         State::InstalledLatest(_) => true,
-        _ => remote_version == "0.0.0" || !has_os_match,
+        State::InstalledUpdate(_, _) | State::InstalledDowngrade(_, _) => false,
+        State::NotInstalled(l) => l.is_none(),
     };
 
     let hide_remove = matches!(&state, State::NotInstalled(_));
@@ -271,26 +920,70 @@ pub fn item(props: &ItemProps) -> Html {
     };
 
     let install_uprade_txt = match &state {
-        State::InstalledUpdate(_, _) => "Update",
-        State::NotInstalled(_) => "Install",
-        _ => "Woops!",
+        State::InstalledUpdate(_, l) if props.local_yanked => format!("Move to v{l}"),
+        State::InstalledUpdate(_, _) => "Update".to_string(),
+        State::InstalledDowngrade(_, l) if props.local_yanked => format!("Move to v{l}"),
+        State::InstalledDowngrade(_, l) => format!("Go back to v{l}"),
+        State::NotInstalled(_) => "Install".to_string(),
+        _ => "Woops!".to_string(),
     };
 
-    let onclick_install = {
+    let onclick_adopt = {
         let id = id.clone();
         let cb = props.set_progress_message.clone();
         let install_error = install_error.clone();
         Callback::from(move |e: MouseEvent| {
             e.prevent_default();
 
+            let id = id.clone();
+            let cb = cb.clone();
+            let install_error = install_error.clone();
+            spawn_local(async move {
+                let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                    id: (*id).clone(),
+                })
+                .unwrap();
+                match invoke("adopt_app", args).await {
+                    Ok(_) => {
+                        install_error.set(String::new());
+                        cb.emit((None, true));
+                    }
+                    Err(e) => install_error.set(e.as_string().unwrap()),
+                }
+            });
+        })
+    };
+
+    let existing_notice = props.existing.as_ref().map(|existing| match &existing.version {
+        Some(version) => html! {
+            <p class="item__existing">
+                { format!("v{version} is already in {}, but wasn't installed here. ", existing.directory) }
+                <button class="btn" onclick={ onclick_adopt }>{ "Adopt" }</button>
+            </p>
+        },
+        None => html! {
+            <p class="item__existing">
+                { format!("There is already a copy in {}, but its version couldn't be identified.", existing.directory) }
+            </p>
+        },
+    });
+
+    let start_install = {
+        let id = id.clone();
+        let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
+        let pending_release_notes = pending_release_notes.clone();
+        Callback::from(move |version: Option<String>| {
+            pending_release_notes.set(None);
             cb.emit((Some("Installing...".to_string()), false));
 
             let id = id.clone();
             let cb = cb.clone();
             let install_error = install_error.clone();
             spawn_local(async move {
-                let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                let args = serde_wasm_bindgen::to_value(&InstallArgs {
                     id: (*id).clone(),
+                    version,
                 })
                 .unwrap();
                 let result = invoke("install_app", args).await;
@@ -305,6 +998,153 @@ pub fn item(props: &ItemProps) -> Html {
         })
     };
 
+    let yanked_warning = match (&state, props.local_yanked) {
+        (State::NotInstalled(_), _) | (_, false) => None,
+        (_, true) => {
+            let message = match &props.yank_reason {
+                Some(reason) => format!("This version has been withdrawn: {reason}"),
+                None => "This version has been withdrawn.".to_string(),
+            };
+            let recommendation = match &props.recommended_version {
+                // The main button already moves to the version offered
+                Some(v) if props.remote_version.as_ref() == Some(v) => {
+                    html! { { format!(" We recommend moving to v{v}.") } }
+                }
+                Some(v) => {
+                    let onclick_move_to = {
+                        let start_install = start_install.clone();
+                        let v = v.clone();
+                        Callback::from(move |e: MouseEvent| {
+                            e.prevent_default();
+                            start_install.emit(Some(v.clone()));
+                        })
+                    };
+                    html! {
+                        <>
+                            { format!(" We recommend moving to v{v}. ") }
+                            <button class="btn" onclick={ onclick_move_to }>{ format!("Move to v{v}") }</button>
+                        </>
+                    }
+                }
+                None => html! { { " There is no other version to move to yet." } },
+            };
+            Some(html! {
+                <p class="item__yanked">{ message }{ recommendation }</p>
+            })
+        }
+    };
+
+    let onclick_install = {
+        let id = id.clone();
+        let is_update = matches!(state, State::InstalledUpdate(_, _));
+        let existing = props.existing.clone();
+        let start_install = start_install.clone();
+        let pending_release_notes = pending_release_notes.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            if let Some(existing) = existing.clone() {
+                // Make sure a copy that was put there some other way isn't replaced by accident
+                let start_install = start_install.clone();
+                spawn_local(async move {
+                    let response = confirm(
+                        &format!(
+                            "There is already a copy in {}. Installing will replace it.",
+                            existing.directory
+                        ),
+                        serde_wasm_bindgen::to_value(&DialogOptions {
+                            title: "Replace existing copy?",
+                            kind: "warning",
+                        })
+                        .unwrap(),
+                    )
+                    .await;
+                    // SAFETY: confirm always returns bool
+                    if response.as_bool().unwrap() {
+                        start_install.emit(None);
+                    }
+                });
+                return;
+            }
+            if !is_update {
+                start_install.emit(None);
+                return;
+            }
+
+            // Show what the update brings before going ahead, if there is anything to show
+            let id = id.clone();
+            let start_install = start_install.clone();
+            let pending_release_notes = pending_release_notes.clone();
+            spawn_local(async move {
+                let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                    id: (*id).clone(),
+                })
+                .unwrap();
+                let notes: Vec<ReleaseNotes> = invoke("release_notes", args)
+                    .await
+                    .ok()
+                    .and_then(|v| serde_wasm_bindgen::from_value(v).ok())
+                    .unwrap_or_default();
+                if notes.iter().any(|n| n.notes.is_some() || n.url.is_some()) {
+                    pending_release_notes.set(Some(notes));
+                } else {
+                    start_install.emit(None);
+                }
+            });
+        })
+    };
+
+    let release_notes_modal = pending_release_notes.as_ref().map(|notes| {
+        let onclick_confirm = {
+            let start_install = start_install.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.prevent_default();
+                start_install.emit(None);
+            })
+        };
+        let onclick_cancel = {
+            let pending_release_notes = pending_release_notes.clone();
+            Callback::from(move |e: MouseEvent| {
+                e.prevent_default();
+                pending_release_notes.set(None);
+            })
+        };
+        let versions: Vec<_> = notes
+            .iter()
+            .map(|n| {
+                let body = match (&n.notes, &n.url) {
+                    (_, Some(url)) => html! {
+                        <p><a href={ url.clone() } target="_blank">{ "Read the release notes" }</a></p>
+                    },
+                    (Some(notes), None) => render_markdown(notes),
+                    (None, None) => html! { <p class="release-notes__empty">{ "No release notes." }</p> },
+                };
+                html! {
+                    <section class="release-notes__version">
+                        <h3>
+                            { format!("v{}", n.version) }
+                            if let Some(date) = &n.released_at {
+                                <span class="release-notes__date">{ date }</span>
+                            }
+                        </h3>
+                        { body }
+                    </section>
+                }
+            })
+            .collect();
+        html! {
+            <div class="modal">
+                <div class="modal__content">
+                    <h2>{ format!("What's new in {}", props.name) }</h2>
+                    <div class="release-notes">{ versions }</div>
+                    <div class="modal__buttons">
+                        <button class="btn" onclick={ onclick_cancel }>{ "Cancel" }</button>
+                        <button class="btn" onclick={ onclick_confirm }>{ "Update" }</button>
+                    </div>
+                </div>
+            </div>
+        }
+    });
+
     let onclick_start = {
         let id = id.clone();
         let install_error = install_error.clone();
@@ -326,16 +1166,139 @@ pub fn item(props: &ItemProps) -> Html {
         })
     };
 
+    let onclick_move = {
+        let id = id.clone();
+        let name = props.name.clone();
+        let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+
+            let id = id.clone();
+            let name = name.clone();
+            let cb = cb.clone();
+            let install_error = install_error.clone();
+            spawn_local(async move {
+                let Some(root) = pick_directory(&format!("Move {name} to")).await else {
+                    return;
+                };
+                cb.emit((Some(format!("Moving {name}...")), false));
+                let args = serde_wasm_bindgen::to_value(&MoveArgs {
+                    id: (*id).clone(),
+                    root: Some(root),
+                })
+                .unwrap();
+                match invoke("move_app", args).await {
+                    Ok(_) => {
+                        install_error.set(String::new());
+                        cb.emit((None, true));
+                    }
+                    Err(e) => {
+                        install_error.set(e.as_string().unwrap());
+                        cb.emit((None, false));
+                    }
+                }
+            });
+        })
+    };
+
+    let onclick_verify = {
+        let id = id.clone();
+        let name = props.name.clone();
+        let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+
+            let id = id.clone();
+            let name = name.clone();
+            let cb = cb.clone();
+            let install_error = install_error.clone();
+            spawn_local(async move {
+                let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                    id: (*id).clone(),
+                })
+                .unwrap();
+                let verification: Option<Verification> = match invoke("verify_app", args).await {
+                    Ok(v) => serde_wasm_bindgen::from_value(v).unwrap(),
+                    Err(e) => {
+                        install_error.set(e.as_string().unwrap());
+                        return;
+                    }
+                };
+
+                let message = match verification {
+                    None => format!("{name} was installed before its files were recorded, so it can't be checked. Do you want to repair it?"),
+                    Some(v) if v.missing.is_empty() && v.modified.is_empty() => {
+                        dialog(
+                            &format!("All files for {name} are intact."),
+                            serde_wasm_bindgen::to_value(&DialogOptions {
+                                title: "Verification complete",
+                                kind: "info",
+                            })
+                            .unwrap(),
+                        );
+                        return;
+                    }
+                    Some(v) => {
+                        let mut message = format!(
+                            "{name} has {} missing and {} modified files.",
+                            v.missing.len(),
+                            v.modified.len()
+                        );
+                        if !v.extra.is_empty() {
+                            message.push_str(&format!(
+                                " {} files that weren't installed by AngelSuite will be left alone.",
+                                v.extra.len()
+                            ));
+                        }
+                        message.push_str(" Do you want to repair it?");
+                        message
+                    }
+                };
+                let response = confirm(
+                    &message,
+                    serde_wasm_bindgen::to_value(&DialogOptions {
+                        title: "Repair?",
+                        kind: "warning",
+                    })
+                    .unwrap(),
+                )
+                .await;
+                // SAFETY: confirm always returns bool
+                if !response.as_bool().unwrap() {
+                    return;
+                }
+
+                cb.emit((Some("Repairing...".to_string()), false));
+                let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                    id: (*id).clone(),
+                })
+                .unwrap();
+                match invoke("repair_app", args).await {
+                    Ok(_) => cb.emit((None, true)),
+                    Err(e) => {
+                        install_error.set(e.as_string().unwrap());
+                        cb.emit((None, false));
+                    }
+                }
+            });
+        })
+    };
+
     let onclick_remove = {
         let id = id.clone();
         let name = props.name.clone();
+        let has_user_data = props.has_user_data;
         let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
         Callback::from(move |e: MouseEvent| {
             e.prevent_default();
 
             let id = id.clone();
             let name = name.clone();
             let cb = cb.clone();
+            let install_error = install_error.clone();
             wasm_bindgen_futures::spawn_local(async move {
                 let response = confirm(
                     &format!("Are you sure you want to remove {name}?"),
@@ -348,51 +1311,118 @@ pub fn item(props: &ItemProps) -> Html {
                 .await;
                 // SAFETY: confirm always returns bool
                 if response.as_bool().unwrap() {
+                    let remove_user_data = if has_user_data {
+                        let response = confirm(
+                            &format!("Do you want to keep your data for {name}? It will be restored if you install it again."),
+                            serde_wasm_bindgen::to_value(&ConfirmOptions {
+                                title: "Keep your data?",
+                                kind: "warning",
+                                ok_label: "Keep Data",
+                                cancel_label: "Remove Everything",
+                            })
+                            .unwrap(),
+                        )
+                        .await;
+                        // SAFETY: confirm always returns bool
+                        !response.as_bool().unwrap()
+                    } else {
+                        true
+                    };
+
                     cb.emit((Some("Removing...".to_string()), false));
 
                     let id = id.clone();
                     let cb = cb.clone();
+                    let install_error = install_error.clone();
                     spawn_local(async move {
-                        let args = serde_wasm_bindgen::to_value(&StartInstallUpgradeRemoveArgs {
+                        let args = serde_wasm_bindgen::to_value(&RemoveArgs {
                             id: (*id).clone(),
+                            remove_user_data,
                         })
                         .unwrap();
 
-                        // SAFETY: this exists
-                        invoke("remove_app", args).await.unwrap();
-
-                        cb.emit((None, true));
+                        match invoke("remove_app", args).await {
+                            Ok(_) => {
+                                install_error.set(String::new());
+                                cb.emit((None, true));
+                            }
+                            Err(e) => {
+                                install_error.set(e.as_string().unwrap());
+                                cb.emit((None, false));
+                            }
+                        }
                     });
                 }
             });
         })
     };
 
-    let onchange_prerelease = {
-        let allow_prereleases = allow_prereleases.clone();
+    let onchange_channel = {
+        let id = id.clone();
+        let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
         Callback::from(move |e: Event| {
-            e.prevent_default();
-            // Update available latest version
-            allow_prereleases.set(!*allow_prereleases);
+            let channel = e
+                .target_unchecked_into::<web_sys::HtmlSelectElement>()
+                .value();
+            let id = id.clone();
+            let cb = cb.clone();
+            let install_error = install_error.clone();
+            spawn_local(async move {
+                let args = serde_wasm_bindgen::to_value(&SetChannelArgs {
+                    id: (*id).clone(),
+                    channel,
+                })
+                .unwrap();
+                // Reload so the versions shown are from the new channel
+                match invoke("set_channel", args).await {
+                    Ok(_) => {
+                        install_error.set(String::new());
+                        cb.emit((None, true));
+                    }
+                    Err(e) => install_error.set(e.as_string().unwrap()),
+                }
+            });
         })
     };
 
-    {
-        let allow_prereleases = allow_prereleases.clone();
+    let onchange_pin = {
         let id = id.clone();
-        use_effect_with(allow_prereleases.clone(), move |_| {
+        let cb = props.set_progress_message.clone();
+        let install_error = install_error.clone();
+        Callback::from(move |e: Event| {
+            let pin = e
+                .target_unchecked_into::<web_sys::HtmlInputElement>()
+                .value();
+            let id = id.clone();
+            let cb = cb.clone();
+            let install_error = install_error.clone();
             spawn_local(async move {
-                // Trigger event to updated `installer.json`
-                let args = serde_wasm_bindgen::to_value(&SetPrereleaseArgs {
+                let args = serde_wasm_bindgen::to_value(&SetPinArgs {
                     id: (*id).clone(),
-                    allow_prerelease: *allow_prereleases,
+                    pin: Some(pin),
                 })
                 .unwrap();
-                // SAFETY: function exists
-                invoke("set_prerelease", args).await.unwrap();
+                match invoke("set_pin", args).await {
+                    Ok(_) => {
+                        install_error.set(String::new());
+                        cb.emit((None, true));
+                    }
+                    Err(e) => install_error.set(e.as_string().unwrap()),
+                }
             });
-        });
-    }
+        })
+    };
+
+    let channel_options: Vec<_> = props
+        .channels
+        .iter()
+        .map(|c| {
+            html! {
+                <option value={ c.id.clone() } selected={ props.channel.as_ref() == Some(&c.id) }>{ &c.name }</option>
+            }
+        })
+        .collect();
 
     let icon = props.icon.as_ref().map(|ic| {
         html! {
@@ -405,14 +1435,109 @@ pub fn item(props: &ItemProps) -> Html {
             <p class="item__name">{ icon }{ &props.name }</p>
             <p class="item__state">{ &state_str }</p>
             <p class="item__description">{ &props.description }</p>
-            <label class="item__prerelease">
-                <input type="checkbox" name="allow_prerelease" onchange={ onchange_prerelease } checked={*allow_prereleases} />
-                { "Use Prerelease Versions" }
+            <label class="item__channel" hidden={ props.channels.len() < 2 }>
+                { "Channel " }
+                <select name="channel" onchange={ onchange_channel }>
+                    { channel_options }
+                </select>
             </label>
+            <label class="item__pin">
+                { "Hold at versions " }
+                <input type="text" name="pin" size="8" placeholder="e.g. 2.x" onchange={ onchange_pin } value={ props.pin.clone().unwrap_or_default() } />
+            </label>
+            <p class="item__location" hidden={ props.install_directory.is_none() }>{ props.install_directory.clone().unwrap_or_default() }</p>
+            { existing_notice }
+            { yanked_warning }
             <p style="color: red;">{ &*install_error }</p>
             <button class="btn" onclick={ onclick_start } hidden={ hide_start }>{ "Start" }</button>
             <button class="btn" onclick={ onclick_install } hidden={ hide_install_upgrade }>{ install_uprade_txt }</button>
+            <button class="btn" onclick={ onclick_move } hidden={ props.install_directory.is_none() }>{ if hide_remove { "Change Location" } else { "Move" } }</button>
+            <button class="btn" onclick={ onclick_verify } hidden={ hide_remove }>{ "Verify" }</button>
             <button class="btn" onclick={ onclick_remove } hidden={ hide_remove }>{ "Remove" }</button>
+            { release_notes_modal }
         </div>
     }
 }
+
+/// Render Markdown from the manifest, dropping any raw HTML in it. Only web
+/// links are kept, and they open in the browser rather than the installer.
+/// Images are shown as their alt text, so nothing is fetched.
+fn render_markdown(markdown: &str) -> Html {
+    use pulldown_cmark::{Event, Tag, TagEnd};
+
+    // Whether each open link was kept, so its end can be kept or dropped to match
+    let mut kept_links = vec![];
+    let parser = Parser::new(markdown).filter_map(|event| match event {
+        Event::Html(_) | Event::InlineHtml(_) => None,
+        Event::Start(Tag::Image { .. }) | Event::End(TagEnd::Image) => None,
+        Event::Start(Tag::Link { dest_url, .. }) => {
+            let keep = is_web_link(&dest_url);
+            kept_links.push(keep);
+            keep.then(|| {
+                Event::InlineHtml(
+                    format!(
+                        r#"<a href="{}" target="_blank" rel="noopener noreferrer">"#,
+                        escape_attribute(&dest_url)
+                    )
+                    .into(),
+                )
+            })
+        }
+        Event::End(TagEnd::Link) => kept_links
+            .pop()
+            .unwrap_or(false)
+            .then(|| Event::InlineHtml("</a>".into())),
+        event => Some(event),
+    });
+    let mut rendered = String::new();
+    pulldown_cmark::html::push_html(&mut rendered, parser);
+    Html::from_html_unchecked(rendered.into())
+}
+
+/// Is `url` an address on the web, rather than a script or something local?
+fn is_web_link(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// Escape text to go in a quoted HTML attribute.
+fn escape_attribute(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Format a number of bytes for humans.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for u in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = u;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// Format a number of seconds as a rough age for humans.
+fn format_age(seconds: u64) -> String {
+    let (count, unit) = match seconds {
+        0..60 => return "less than a minute".to_string(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}